@@ -0,0 +1,140 @@
+use crate::{
+    constants::{AUTH_NONCE_LEN, MIN_AUTH_KEY_LEN},
+    protocol::{Authenticate, Challenge},
+};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{collections::HashMap, fmt};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Client authentication settings, the `[auth]` section of a
+/// [`crate::config::ServerConfig`].
+///
+/// Every identity shares a secret key with the server. After the version
+/// handshake the server sends a random [`Challenge`] which the client must
+/// answer with an HMAC-SHA256 of the nonce and its identity, so the key
+/// itself never crosses the network.
+///
+/// ```toml
+/// [auth.keys]
+/// ci-runner = "a long, randomly generated secret"
+/// backup = "another long, randomly generated secret"
+/// ```
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Shared keys, by client identity.
+    pub keys: HashMap<String, String>,
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("identities", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl AuthConfig {
+    /// Adds an identity and its shared key.
+    pub fn with_key(mut self, identity: impl Into<String>, key: impl Into<String>) -> Self {
+        self.keys.insert(identity.into(), key.into());
+        self
+    }
+
+    /// Describes the first unusable entry, if any.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.keys.is_empty() {
+            return Err(String::from("auth.keys must list at least one identity"));
+        }
+        for (identity, key) in &self.keys {
+            if identity.is_empty() {
+                return Err(String::from("auth.keys has an empty identity"));
+            }
+            if key.len() < MIN_AUTH_KEY_LEN {
+                return Err(format!(
+                    "auth key of {} must be at least {} bytes long",
+                    identity, MIN_AUTH_KEY_LEN
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a challenge with a fresh random nonce.
+    pub(crate) fn challenge(&self) -> Challenge {
+        let mut nonce = vec![0u8; AUTH_NONCE_LEN];
+        rand::rng().fill_bytes(&mut nonce);
+        Challenge { nonce }
+    }
+
+    /// Returns `true` if `answer` proves knowledge of the key of its identity.
+    ///
+    /// The comparison runs in constant time.
+    pub(crate) fn verify(&self, challenge: &Challenge, answer: &Authenticate) -> bool {
+        match self.keys.get(&answer.identity) {
+            Some(key) => mac(key.as_bytes(), challenge, &answer.identity)
+                .verify_slice(&answer.mac)
+                .is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// An identity and its shared key, used by a client to answer a [`Challenge`].
+///
+/// # Examples
+/// ```
+/// use task_scheduler::{auth::Credentials, client::Client};
+///
+/// let credentials = Credentials::new("ci-runner", "a long, randomly generated secret");
+/// let client = Client::builder("127.0.0.1:8080").credentials(credentials).build();
+/// ```
+#[derive(Clone)]
+pub struct Credentials {
+    identity: String,
+    key: Vec<u8>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("identity", &self.identity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Credentials {
+    /// Creates credentials for `identity` holding the shared `key`.
+    pub fn new(identity: impl Into<String>, key: impl AsRef<[u8]>) -> Self {
+        Self {
+            identity: identity.into(),
+            key: key.as_ref().to_vec(),
+        }
+    }
+
+    /// Returns the identity these credentials authenticate as.
+    #[inline]
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Computes the answer to a server's challenge.
+    pub fn respond(&self, challenge: &Challenge) -> Authenticate {
+        Authenticate {
+            identity: self.identity.clone(),
+            mac: mac(&self.key, challenge, &self.identity).finalize().into_bytes().to_vec(),
+        }
+    }
+}
+
+/// HMAC-SHA256 over the nonce followed by the identity.
+fn mac(key: &[u8], challenge: &Challenge, identity: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&challenge.nonce);
+    mac.update(identity.as_bytes());
+    mac
+}
@@ -0,0 +1,566 @@
+use crate::{
+    FilePath, HashAlgorithms,
+    auth::Credentials,
+    config::ConfigError,
+    tls::ClientTls,
+    protocol::{
+        FailureReason, HashingPacket, Priority, Progress, ProtocolError, ProtocolMessage,
+        QuotaError, RequestId, TaskRequest, TaskResponse, Welcome, client_handshake_with,
+        read_protocol, write_protocol,
+    },
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader},
+    net::TcpStream,
+    sync::{Mutex, mpsc, oneshot},
+    task::JoinSet,
+    time::timeout,
+};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// Represents failures encountered by a [`Client`] call.
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// The connection could not be established or the handshake failed.
+    #[error("Protocol error: {0}")]
+    Protocol(#[from] ProtocolError),
+
+    /// The connection was lost before the response arrived.
+    #[error("Connection to the server was lost")]
+    ConnectionLost,
+
+    /// No response arrived before the per-call deadline.
+    #[error("Request has timed out")]
+    Timeout,
+
+    /// The TLS settings cannot be applied to the server address.
+    #[error("TLS setup failed: {0}")]
+    Tls(#[from] ConfigError),
+
+    /// The server does not advertise the requested algorithm.
+    #[error("Hash algorithm {0:?} is not supported by the server")]
+    UnsupportedAlgorithm(HashAlgorithms),
+
+    /// The server's queue was full and the request was not run.
+    #[error("Server is busy ({queue_depth} tasks queued), retry after {retry_after:?}")]
+    Busy {
+        /// How long to wait before sending the request again.
+        retry_after: Duration,
+        /// Number of tasks waiting on the server when the request was turned away.
+        queue_depth: u32,
+    },
+
+    /// The client exceeded one of the quotas the server applies to it, and
+    /// the request was not run.
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(QuotaError),
+
+    /// The server processed the request and reported a failure.
+    #[error("Task failed ({reason:?}): {}", message.as_deref().unwrap_or("no details"))]
+    Failed {
+        /// Machine-readable category of the failure.
+        reason: FailureReason,
+        /// Optional human-readable details sent by the server.
+        message: Option<String>,
+    },
+}
+
+impl ClientError {
+    /// Returns `true` if the same request may succeed when sent again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::ConnectionLost
+            | ClientError::Timeout
+            | ClientError::Busy { .. }
+            | ClientError::QuotaExceeded(_) => true,
+            ClientError::Failed { reason, .. } => reason.is_retryable(),
+            ClientError::Protocol(_) | ClientError::Tls(_) | ClientError::UnsupportedAlgorithm(_) => {
+                false
+            }
+        }
+    }
+}
+
+/// Builder for a [`Client`].
+///
+/// # Examples
+/// ```no_run
+/// # async fn demo() -> Result<(), task_scheduler::client::ClientError> {
+/// use std::time::Duration;
+/// use task_scheduler::{FilePath, HashAlgorithms, client::Client};
+///
+/// let client = Client::builder("127.0.0.1:8080")
+///     .pool_size(4)
+///     .request_timeout(Duration::from_secs(30))
+///     .build();
+/// let digest = client
+///     .hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    addr: String,
+    pool_size: usize,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    retries: u32,
+    tls: Option<ClientTls>,
+    credentials: Option<Credentials>,
+    priority: Priority,
+}
+
+impl ClientBuilder {
+    /// Number of connections opened to the server, at least one.
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size.max(1);
+        self
+    }
+
+    /// Deadline for establishing a connection and completing the handshake.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Default deadline of a call, used when none is given explicitly.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Number of times a request is re-sent after its connection was lost.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Connects over TLS instead of plaintext TCP.
+    pub fn tls(mut self, tls: ClientTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Authenticates every connection with `credentials`, for servers
+    /// requiring it.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Priority of the requests sent by the client, [`Priority::Normal`]
+    /// unless set.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Creates the [`Client`]. Connections are opened lazily on first use.
+    pub fn build(self) -> Client {
+        let slots = (0..self.pool_size).map(|_| Mutex::new(None)).collect();
+        Client {
+            inner: Arc::new(ClientInner {
+                config: self,
+                slots,
+                next_slot: AtomicUsize::new(0),
+            }),
+        }
+    }
+}
+
+/// An asynchronous client for the orchestrator protocol.
+///
+/// A `Client` owns a small pool of connections, each of which pipelines any
+/// number of concurrent requests. Broken connections are replaced on the next
+/// call, and requests lost with them are transparently re-sent. Cloning a
+/// `Client` is cheap and shares the pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: Arc<ClientInner>,
+}
+
+#[derive(Debug)]
+struct ClientInner {
+    config: ClientBuilder,
+    slots: Vec<Mutex<Option<Arc<Connection>>>>,
+    next_slot: AtomicUsize,
+}
+
+impl Client {
+    /// Starts building a client for the server listening on `addr`.
+    ///
+    /// `addr` is either a `host:port` TCP address or, for a server on the
+    /// same host, `unix:` followed by the path of its socket. TLS settings do
+    /// not apply to Unix sockets.
+    pub fn builder(addr: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            addr: addr.into(),
+            pool_size: 1,
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(60),
+            retries: 1,
+            tls: None,
+            credentials: None,
+            priority: Priority::Normal,
+        }
+    }
+
+    /// Hashes `path` with `algorithm` and returns the hex-encoded digest.
+    ///
+    /// # Errors
+    /// Returns [`ClientError::Failed`] with the server's reason if the task
+    /// failed, or a transport-level [`ClientError`] otherwise.
+    pub async fn hash(&self, path: FilePath, algorithm: HashAlgorithms) -> Result<String, ClientError> {
+        let packet = HashingPacket {
+            algorithm,
+            path,
+            output_len: None,
+            progress: false,
+        };
+        self.submit(packet, self.inner.config.request_timeout).await
+    }
+
+    /// Submits an arbitrary [`HashingPacket`] with its own deadline.
+    ///
+    /// Dropping the returned future before it completes also asks the
+    /// server to cancel the task.
+    ///
+    /// # Errors
+    /// Returns [`ClientError::Timeout`] if no response arrived in time, in
+    /// which case the server is asked to cancel the task.
+    pub async fn submit(&self, packet: HashingPacket, deadline: Duration) -> Result<String, ClientError> {
+        self.send(packet, deadline, None).await
+    }
+
+    /// Submits `packet` like [`Client::submit`], asking the server for
+    /// [`Progress`] frames and calling `on_progress` with each of them.
+    ///
+    /// # Errors
+    /// Same as [`Client::submit`].
+    pub async fn submit_with_progress<F>(
+        &self,
+        packet: HashingPacket,
+        deadline: Duration,
+        mut on_progress: F,
+    ) -> Result<String, ClientError>
+    where
+        F: FnMut(&Progress) + Send,
+    {
+        self.send(packet, deadline, Some(&mut on_progress)).await
+    }
+
+    /// Sends `packet`, re-sending it when its connection was lost.
+    async fn send<'f>(
+        &self,
+        packet: HashingPacket,
+        deadline: Duration,
+        mut on_progress: Option<&mut (dyn FnMut(&Progress) + Send + 'f)>,
+    ) -> Result<String, ClientError> {
+        let mut attempts = 0;
+        loop {
+            let connection = self.connection().await?;
+            if !connection.welcome.algorithms.contains(&packet.algorithm) {
+                return Err(ClientError::UnsupportedAlgorithm(packet.algorithm));
+            }
+
+            let priority = self.inner.config.priority;
+            match connection.request(&packet, priority, deadline, on_progress.as_deref_mut()).await {
+                Err(ClientError::ConnectionLost) if attempts < self.inner.config.retries => {
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Submits many packets at once and returns their results in order.
+    ///
+    /// Requests are pipelined over the pool, so the whole batch completes in
+    /// roughly the time of its slowest task.
+    pub async fn hash_batch(&self, packets: Vec<HashingPacket>) -> Vec<Result<String, ClientError>> {
+        let deadline = self.inner.config.request_timeout;
+        let mut tasks = JoinSet::new();
+        for (index, packet) in packets.into_iter().enumerate() {
+            let client = self.clone();
+            tasks.spawn(async move { (index, client.submit(packet, deadline).await) });
+        }
+
+        let mut results: Vec<Option<Result<String, ClientError>>> = Vec::new();
+        results.resize_with(tasks.len(), || None);
+        while let Some(joined) = tasks.join_next().await {
+            if let Ok((index, result)) = joined {
+                results[index] = Some(result);
+            }
+        }
+        results
+            .into_iter()
+            .map(|r| r.unwrap_or(Err(ClientError::ConnectionLost)))
+            .collect()
+    }
+
+    /// Returns the capabilities announced by the server, connecting if needed.
+    pub async fn welcome(&self) -> Result<Welcome, ClientError> {
+        Ok(self.connection().await?.welcome.clone())
+    }
+
+    /// Picks the next pool slot and (re)connects it if it is empty or broken.
+    async fn connection(&self) -> Result<Arc<Connection>, ClientError> {
+        let index = self.inner.next_slot.fetch_add(1, Ordering::Relaxed) % self.inner.slots.len();
+        let mut slot = self.inner.slots[index].lock().await;
+
+        if let Some(connection) = slot.as_ref()
+            && connection.is_alive()
+        {
+            return Ok(Arc::clone(connection));
+        }
+
+        let config = &self.inner.config;
+        let connection = timeout(config.connect_timeout, async {
+            #[cfg(unix)]
+            if let Some(path) = config.addr.strip_prefix("unix:") {
+                let stream = UnixStream::connect(path).await.map_err(ProtocolError::Io)?;
+                return Connection::establish(stream, config.credentials.as_ref()).await;
+            }
+            let stream = TcpStream::connect(&config.addr).await.map_err(ProtocolError::Io)?;
+            let _ = stream.set_nodelay(true);
+            match &config.tls {
+                Some(tls) => {
+                    let (connector, name) = tls.connector(&config.addr)?;
+                    let stream = connector
+                        .connect(name, stream)
+                        .await
+                        .map_err(ProtocolError::Io)?;
+                    Connection::establish(stream, config.credentials.as_ref()).await
+                }
+                None => Connection::establish(stream, config.credentials.as_ref()).await,
+            }
+        })
+        .await
+        .map_err(|_| ClientError::Timeout)??;
+
+        *slot = Some(Arc::clone(&connection));
+        Ok(connection)
+    }
+}
+
+/// A caller waiting for the response to one of its requests.
+struct Waiter {
+    response: oneshot::Sender<TaskResponse>,
+    progress: Option<mpsc::UnboundedSender<Progress>>,
+}
+
+type Pending = Arc<std::sync::Mutex<HashMap<RequestId, Waiter>>>;
+type Writer = Arc<Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+
+/// A single multiplexed connection to the server.
+struct Connection {
+    welcome: Welcome,
+    writer: Writer,
+    pending: Pending,
+    next_id: AtomicU64,
+    alive: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection")
+            .field("alive", &self.is_alive())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Connection {
+    /// Performs the handshake and starts the task dispatching responses.
+    async fn establish<S>(mut stream: S, credentials: Option<&Credentials>) -> Result<Arc<Self>, ClientError>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let welcome = client_handshake_with(&mut stream, credentials).await?;
+        let (reader, writer) = tokio::io::split(stream);
+
+        let pending: Pending = Arc::default();
+        let alive = Arc::new(AtomicBool::new(true));
+        let writer: Writer = Arc::new(Mutex::new(Box::new(writer)));
+        tokio::spawn(dispatch_responses(
+            BufReader::new(reader),
+            Arc::clone(&writer),
+            Arc::clone(&pending),
+            Arc::clone(&alive),
+        ));
+
+        Ok(Arc::new(Self {
+            welcome,
+            writer,
+            pending,
+            next_id: AtomicU64::new(0),
+            alive,
+        }))
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Acquire)
+    }
+
+    /// Sends one request and waits for the response carrying its id, passing
+    /// the progress frames received meanwhile to `on_progress`.
+    async fn request<'f>(
+        &self,
+        packet: &HashingPacket,
+        priority: Priority,
+        deadline: Duration,
+        mut on_progress: Option<&mut (dyn FnMut(&Progress) + Send + 'f)>,
+    ) -> Result<String, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut packet = packet.clone();
+        packet.progress |= on_progress.is_some();
+        let message = ProtocolMessage::TaskRequest(TaskRequest::HashPacket { id, packet, priority });
+
+        let (tx, mut rx) = oneshot::channel();
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+        let waiter = Waiter {
+            response: tx,
+            progress: on_progress.is_some().then_some(progress_tx),
+        };
+        self.pending.lock().unwrap().insert(id, waiter);
+        let mut guard = PendingGuard {
+            connection: self,
+            id,
+            sent: false,
+        };
+        // The dispatcher flags the connection before clearing the pending map,
+        // so a request registered after that clearing is caught here.
+        if !self.is_alive() {
+            return Err(ClientError::ConnectionLost);
+        }
+
+        let written = write_protocol(&mut *self.writer.lock().await, &message).await;
+        guard.sent = written.is_ok();
+        if let Err(e) = written {
+            return Err(match e {
+                ProtocolError::Io(_) => {
+                    self.alive.store(false, Ordering::Release);
+                    ClientError::ConnectionLost
+                }
+                e => e.into(),
+            });
+        }
+
+        let response = timeout(deadline, async {
+            loop {
+                tokio::select! {
+                    biased;
+                    response = &mut rx => break response,
+                    Some(progress) = progress_rx.recv() => {
+                        if let Some(on_progress) = on_progress.as_deref_mut() {
+                            on_progress(&progress);
+                        }
+                    }
+                }
+            }
+        });
+        let response = match response.await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err(ClientError::ConnectionLost),
+            // The guard cancels the task on the server.
+            Err(_) => return Err(ClientError::Timeout),
+        };
+
+        match response {
+            TaskResponse::Success { digest, .. } => Ok(digest),
+            TaskResponse::Failed { reason, message, .. } => Err(ClientError::Failed { reason, message }),
+            TaskResponse::Busy { retry_after, queue_depth, .. } => Err(ClientError::Busy {
+                retry_after,
+                queue_depth,
+            }),
+            TaskResponse::QuotaExceeded { error, .. } => Err(ClientError::QuotaExceeded(error)),
+        }
+    }
+}
+
+/// Unregisters a request from its [`Connection`] once nobody waits for it.
+///
+/// A request given up before its response arrived, because it timed out or
+/// because the caller dropped its future, is also cancelled on the server to
+/// spare it the work.
+struct PendingGuard<'c> {
+    connection: &'c Connection,
+    id: RequestId,
+    /// Whether the request reached the server.
+    sent: bool,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        let waiting = self.connection.pending.lock().unwrap().remove(&self.id).is_some();
+        if !waiting || !self.sent || !self.connection.is_alive() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let writer = Arc::clone(&self.connection.writer);
+        let cancel = ProtocolMessage::CancelTask(self.id);
+        runtime.spawn(async move {
+            let _ = write_protocol(&mut *writer.lock().await, &cancel).await;
+        });
+    }
+}
+
+/// Routes every response of a connection to the caller waiting for its id,
+/// and answers the server's heartbeats so that idle connections stay open.
+///
+/// When the connection breaks, the connection is flagged as dead and every
+/// pending caller is woken up with [`ClientError::ConnectionLost`].
+async fn dispatch_responses<R>(
+    mut reader: BufReader<R>,
+    writer: Writer,
+    pending: Pending,
+    alive: Arc<AtomicBool>,
+) where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        // Wait for the next frame without a deadline: responses to slow tasks
+        // may take arbitrarily long, only the frame itself must arrive promptly.
+        match reader.fill_buf().await {
+            Ok([]) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        match read_protocol(&mut reader).await {
+            Ok(ProtocolMessage::TaskResponse(response)) => {
+                if let Some(waiter) = pending.lock().unwrap().remove(&response.id()) {
+                    let _ = waiter.response.send(response);
+                }
+            }
+            Ok(ProtocolMessage::Progress(progress)) => {
+                if let Some(Waiter { progress: Some(tx), .. }) = pending.lock().unwrap().get(&progress.id) {
+                    let _ = tx.send(progress);
+                }
+            }
+            Ok(ProtocolMessage::Ping(token)) => {
+                let pong = ProtocolMessage::Pong(token);
+                if write_protocol(&mut *writer.lock().await, &pong).await.is_err() {
+                    break;
+                }
+            }
+            // A server error frame is always the last one of a connection.
+            Ok(ProtocolMessage::Error(_)) => break,
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+    }
+
+    alive.store(false, Ordering::Release);
+    pending.lock().unwrap().clear();
+}
@@ -0,0 +1,578 @@
+use crate::{
+    auth::AuthConfig,
+    constants::{DEFAULT_HASH_BUFFER_SIZE, MAX_CONNECTIONS, MAX_IN_FLIGHT_REQUESTS, MAX_PACKET_SIZE},
+    protocol::FrameConfig,
+    queue::{AdmissionConfig, SchedulingConfig},
+    quota::QuotaConfig,
+    remote::RemoteConfig,
+    sandbox::SandboxConfig,
+    tls::TlsConfig,
+};
+#[cfg(unix)]
+use crate::unix::UnixConfig;
+use serde::Deserialize;
+use std::{fs, io, path::Path, str::FromStr, time::Duration};
+
+/// Represents failures encountered when loading a [`ServerConfig`].
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    #[error("Cannot read configuration: {0}")]
+    Io(#[from] io::Error),
+
+    /// The configuration file is not valid TOML or has unknown keys.
+    #[error("Invalid configuration file: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    /// A value is syntactically valid but unusable (e.g. zero workers).
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+
+    /// A certificate or key could not be loaded or does not fit the TLS setup.
+    #[error("Invalid TLS configuration: {0}")]
+    Tls(String),
+}
+
+impl From<rustls::Error> for ConfigError {
+    fn from(e: rustls::Error) -> Self {
+        ConfigError::Tls(e.to_string())
+    }
+}
+
+/// Settings of an orchestrator instance.
+///
+/// Every field has a default, so a configuration file only needs to list
+/// the values it overrides:
+///
+/// ```toml
+/// bind = "0.0.0.0:8080"
+/// workers = 16
+/// queue_depth = 500
+/// read_timeout = "10s"
+/// heartbeat_interval = "15s"
+/// idle_timeout = "1m"
+/// max_connections = 4096
+/// max_connections_per_ip = 64
+/// hash_buffer_size = 65536
+/// task_timeout = "10m"
+/// max_input_size = 10737418240
+/// progress_interval = "1s"
+/// shutdown_timeout = "1m"
+/// metrics_bind = "0.0.0.0:9100"
+///
+/// [unix]
+/// path = "/run/task_scheduler.sock"
+///
+/// [auth.keys]
+/// ci-runner = "a long, randomly generated secret"
+///
+/// [admission]
+/// policy = "reject"
+/// retry_after = "500ms"
+///
+/// [scheduling]
+/// policy = "weighted"
+///
+/// [scheduling.weights]
+/// batch = 2
+///
+/// [quotas.default]
+/// rate = 20.0
+/// max_in_flight = 8
+///
+/// [quotas.tiers.batch]
+/// rate = 200.0
+/// max_in_flight = 64
+///
+/// [quotas.identities]
+/// ci-runner = "batch"
+///
+/// [sandbox]
+/// roots = ["/srv/artifacts"]
+///
+/// [remote]
+/// allowed_hosts = ["downloads.example.com"]
+/// timeout = "2m"
+///
+/// [log]
+/// level = "info,task_scheduler::workers=debug"
+/// format = "json"
+/// ```
+///
+/// Embedders can use [`ServerConfig::builder`] instead.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the TCP listener binds to.
+    pub bind: String,
+    /// Whether to listen on [`ServerConfig::bind`]. Disabling it requires a
+    /// [`ServerConfig::unix`] socket.
+    pub tcp: bool,
+    /// Number of workers hashing concurrently.
+    pub workers: usize,
+    /// Number of tasks that may wait for a free worker before
+    /// [`ServerConfig::admission`] applies.
+    pub queue_depth: usize,
+    /// Deadline for receiving the rest of a frame once its first byte
+    /// arrived, and for completing the handshake.
+    #[serde(with = "humantime_serde")]
+    pub read_timeout: Duration,
+    /// Silence after which the server pings a connection to check that its
+    /// client is still there.
+    #[serde(with = "humantime_serde")]
+    pub heartbeat_interval: Duration,
+    /// Silence after which a connection is closed, pings included. Clients
+    /// answering pings are never idle.
+    #[serde(with = "humantime_serde")]
+    pub idle_timeout: Duration,
+    /// Number of connections served at once; further clients are refused
+    /// with [`crate::protocol::ServerError::TooManyConnections`], or simply
+    /// closed while [`crate::constants::MAX_CONCURRENT_REFUSALS`] refusals
+    /// are already in progress.
+    pub max_connections: usize,
+    /// Number of TCP connections a single IP address may have open at once;
+    /// further ones are refused with
+    /// [`crate::protocol::ServerError::TooManyPeerConnections`]. `None`
+    /// disables the limit.
+    pub max_connections_per_ip: Option<usize>,
+    /// Largest frame payload, in bytes, accepted from a client, at most
+    /// [`MAX_PACKET_SIZE`].
+    pub max_packet_size: usize,
+    /// Number of requests a single connection may have pending at once.
+    pub max_in_flight_requests: usize,
+    /// Size, in bytes, of the buffer each worker streams its input through.
+    pub hash_buffer_size: usize,
+    /// Longest time a task may spend hashing before it is cancelled and
+    /// answered with [`crate::protocol::FailureReason::Timeout`]. `None`
+    /// disables the deadline.
+    #[serde(with = "humantime_serde")]
+    pub task_timeout: Option<Duration>,
+    /// Largest input, in bytes, a task may hash before it is cancelled and
+    /// answered with [`crate::protocol::FailureReason::TooLarge`]. `None`
+    /// disables the limit.
+    pub max_input_size: Option<u64>,
+    /// Time between two [`crate::protocol::Progress`] frames of a task that
+    /// asked for them.
+    #[serde(with = "humantime_serde")]
+    pub progress_interval: Duration,
+    /// How long a shutdown waits for accepted tasks before closing connections.
+    #[serde(with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
+    /// Address of the Prometheus `/metrics` HTTP endpoint. `None` disables it.
+    pub metrics_bind: Option<String>,
+    /// What happens to tasks submitted while the queue is full.
+    pub admission: AdmissionConfig,
+    /// How the workers share out between clients.
+    pub scheduling: SchedulingConfig,
+    /// Rate limits and in-flight quotas applied to each client.
+    pub quotas: QuotaConfig,
+    /// Which [`crate::FilePath::Local`] targets may be hashed.
+    pub sandbox: SandboxConfig,
+    /// How [`crate::FilePath::Remote`] targets are fetched.
+    pub remote: RemoteConfig,
+    /// How the binary reports events.
+    pub log: LogConfig,
+    /// Serves clients over TLS when set; plaintext TCP otherwise.
+    pub tls: Option<TlsConfig>,
+    /// Also serves local clients over a Unix domain socket when set.
+    #[cfg(unix)]
+    pub unix: Option<UnixConfig>,
+    /// Requires every client to authenticate when set.
+    pub auth: Option<AuthConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: String::from("127.0.0.1:8080"),
+            tcp: true,
+            workers: 10,
+            queue_depth: 100,
+            read_timeout: Duration::from_secs(5),
+            heartbeat_interval: Duration::from_secs(15),
+            idle_timeout: Duration::from_secs(60),
+            max_connections: MAX_CONNECTIONS,
+            max_connections_per_ip: None,
+            max_packet_size: MAX_PACKET_SIZE,
+            max_in_flight_requests: MAX_IN_FLIGHT_REQUESTS,
+            hash_buffer_size: DEFAULT_HASH_BUFFER_SIZE,
+            task_timeout: Some(Duration::from_secs(300)),
+            max_input_size: None,
+            progress_interval: Duration::from_secs(1),
+            shutdown_timeout: Duration::from_secs(30),
+            metrics_bind: None,
+            admission: AdmissionConfig::default(),
+            scheduling: SchedulingConfig::default(),
+            quotas: QuotaConfig::default(),
+            sandbox: SandboxConfig::default(),
+            remote: RemoteConfig::default(),
+            log: LogConfig::default(),
+            tls: None,
+            #[cfg(unix)]
+            unix: None,
+            auth: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Starts building a configuration from the defaults.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use task_scheduler::config::ServerConfig;
+    ///
+    /// let config = ServerConfig::builder()
+    ///     .bind("0.0.0.0:9000")
+    ///     .workers(4)
+    ///     .read_timeout(Duration::from_secs(10))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config.workers, 4);
+    /// ```
+    pub fn builder() -> ServerConfigBuilder {
+        ServerConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
+    /// Loads and validates a configuration from a TOML file.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError`] if the file cannot be read, parsed or validated.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml_str(&fs::read_to_string(path)?)
+    }
+
+    /// Parses and validates a configuration from TOML text.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::Parse`] for malformed input and
+    /// [`ConfigError::Invalid`] for unusable values.
+    pub fn from_toml_str(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that the values can actually run a server.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::Invalid`] describing the first offending value.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.workers == 0 {
+            return Err(ConfigError::Invalid(String::from("workers must be at least 1")));
+        }
+        if self.queue_depth == 0 {
+            return Err(ConfigError::Invalid(String::from("queue_depth must be at least 1")));
+        }
+        if self.read_timeout.is_zero() {
+            return Err(ConfigError::Invalid(String::from("read_timeout must not be zero")));
+        }
+        if self.heartbeat_interval.is_zero() || self.heartbeat_interval >= self.idle_timeout {
+            return Err(ConfigError::Invalid(String::from(
+                "heartbeat_interval must be non-zero and shorter than idle_timeout",
+            )));
+        }
+        if self.max_connections == 0 {
+            return Err(ConfigError::Invalid(String::from("max_connections must be at least 1")));
+        }
+        if self.max_connections_per_ip == Some(0) {
+            return Err(ConfigError::Invalid(String::from(
+                "max_connections_per_ip must be at least 1",
+            )));
+        }
+        // Below 1 KiB a request carrying a realistic path would not fit.
+        // Responses are always framed, and read by clients, under
+        // MAX_PACKET_SIZE, so a larger limit would only apply one way.
+        if !(1024..=MAX_PACKET_SIZE).contains(&self.max_packet_size) {
+            return Err(ConfigError::Invalid(format!(
+                "max_packet_size must lie between 1024 and {}",
+                MAX_PACKET_SIZE
+            )));
+        }
+        if self.max_in_flight_requests == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "max_in_flight_requests must be at least 1",
+            )));
+        }
+        if self.hash_buffer_size == 0 {
+            return Err(ConfigError::Invalid(String::from("hash_buffer_size must be at least 1")));
+        }
+        if !self.tcp && !self.has_unix_socket() {
+            return Err(ConfigError::Invalid(String::from(
+                "tcp cannot be disabled without a [unix] socket",
+            )));
+        }
+        #[cfg(unix)]
+        if let Some(unix) = &self.unix
+            && unix.mode > 0o777
+        {
+            return Err(ConfigError::Invalid(format!(
+                "unix.mode {:#o} is not a permission mask",
+                unix.mode
+            )));
+        }
+        if self.task_timeout.is_some_and(|t| t.is_zero()) {
+            return Err(ConfigError::Invalid(String::from("task_timeout must not be zero")));
+        }
+        if self.max_input_size == Some(0) {
+            return Err(ConfigError::Invalid(String::from("max_input_size must be at least 1")));
+        }
+        if self.progress_interval.is_zero() {
+            return Err(ConfigError::Invalid(String::from("progress_interval must not be zero")));
+        }
+        self.quotas.validate().map_err(ConfigError::Invalid)?;
+        self.scheduling.validate(&self.quotas).map_err(ConfigError::Invalid)?;
+        if let Some(auth) = &self.auth {
+            auth.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.level) {
+            return Err(ConfigError::Invalid(format!("log.level is invalid: {}", e)));
+        }
+        Ok(())
+    }
+
+    fn has_unix_socket(&self) -> bool {
+        #[cfg(unix)]
+        return self.unix.is_some();
+        #[cfg(not(unix))]
+        return false;
+    }
+
+    /// Returns the limits applied when reading client frames after the
+    /// handshake, waiting [`ServerConfig::heartbeat_interval`] for each one.
+    #[inline]
+    pub fn frame_config(&self) -> FrameConfig {
+        FrameConfig {
+            max_packet_size: self.max_packet_size,
+            read_timeout: self.read_timeout,
+            idle_timeout: Some(self.heartbeat_interval),
+        }
+    }
+}
+
+/// Settings of the log output of the `serve` command.
+///
+/// The library itself only emits [`tracing`] events; installing a subscriber
+/// is left to the embedding application.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Filter directives in the `RUST_LOG` syntax, e.g. `info` or
+    /// `warn,task_scheduler::workers=debug`. `RUST_LOG` takes precedence.
+    pub level: String,
+    /// Output format of the events.
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: String::from("info"),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+/// Output format of the log events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per event, including the fields of its spans.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(ConfigError::Invalid(format!("unknown log format {}", s))),
+        }
+    }
+}
+
+/// Builder for a [`ServerConfig`], created by [`ServerConfig::builder`].
+///
+/// Unset values keep their [`Default`], and [`ServerConfigBuilder::build`]
+/// applies the same validation as configuration files.
+#[derive(Debug, Clone)]
+pub struct ServerConfigBuilder {
+    config: ServerConfig,
+}
+
+impl ServerConfigBuilder {
+    /// Address the TCP listener binds to.
+    pub fn bind(mut self, bind: impl Into<String>) -> Self {
+        self.config.bind = bind.into();
+        self
+    }
+
+    /// Whether to listen on the TCP address.
+    pub fn tcp(mut self, enabled: bool) -> Self {
+        self.config.tcp = enabled;
+        self
+    }
+
+    /// Number of workers hashing concurrently.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.config.workers = workers;
+        self
+    }
+
+    /// Number of tasks that may wait for a free worker.
+    pub fn queue_depth(mut self, queue_depth: usize) -> Self {
+        self.config.queue_depth = queue_depth;
+        self
+    }
+
+    /// Deadline for receiving the rest of a frame once it started.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+
+    /// Silence after which the server pings a connection.
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.config.heartbeat_interval = interval;
+        self
+    }
+
+    /// Silence after which a connection is closed.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    /// Number of connections served at once.
+    pub fn max_connections(mut self, count: usize) -> Self {
+        self.config.max_connections = count;
+        self
+    }
+
+    /// Number of TCP connections a single IP address may have open at once;
+    /// `None` disables the limit.
+    pub fn max_connections_per_ip(mut self, count: Option<usize>) -> Self {
+        self.config.max_connections_per_ip = count;
+        self
+    }
+
+    /// Largest frame payload, in bytes, accepted from a client, at most
+    /// [`MAX_PACKET_SIZE`].
+    pub fn max_packet_size(mut self, size: usize) -> Self {
+        self.config.max_packet_size = size;
+        self
+    }
+
+    /// Number of requests a single connection may have pending at once.
+    pub fn max_in_flight_requests(mut self, count: usize) -> Self {
+        self.config.max_in_flight_requests = count;
+        self
+    }
+
+    /// Size, in bytes, of the buffer each worker streams its input through.
+    pub fn hash_buffer_size(mut self, size: usize) -> Self {
+        self.config.hash_buffer_size = size;
+        self
+    }
+
+    /// Longest time a task may spend hashing; `None` disables the deadline.
+    pub fn task_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.task_timeout = timeout;
+        self
+    }
+
+    /// Largest input, in bytes, a task may hash; `None` disables the limit.
+    pub fn max_input_size(mut self, size: Option<u64>) -> Self {
+        self.config.max_input_size = size;
+        self
+    }
+
+    /// Time between two progress frames of a task that asked for them.
+    pub fn progress_interval(mut self, interval: Duration) -> Self {
+        self.config.progress_interval = interval;
+        self
+    }
+
+    /// How long a shutdown waits for accepted tasks before closing connections.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Address of the Prometheus `/metrics` HTTP endpoint.
+    pub fn metrics_bind(mut self, bind: impl Into<String>) -> Self {
+        self.config.metrics_bind = Some(bind.into());
+        self
+    }
+
+    /// What happens to tasks submitted while the queue is full.
+    pub fn admission(mut self, admission: AdmissionConfig) -> Self {
+        self.config.admission = admission;
+        self
+    }
+
+    /// How the workers share out between clients.
+    pub fn scheduling(mut self, scheduling: SchedulingConfig) -> Self {
+        self.config.scheduling = scheduling;
+        self
+    }
+
+    /// Rate limits and in-flight quotas applied to each client.
+    pub fn quotas(mut self, quotas: QuotaConfig) -> Self {
+        self.config.quotas = quotas;
+        self
+    }
+
+    /// Which [`crate::FilePath::Local`] targets may be hashed.
+    pub fn sandbox(mut self, sandbox: SandboxConfig) -> Self {
+        self.config.sandbox = sandbox;
+        self
+    }
+
+    /// How [`crate::FilePath::Remote`] targets are fetched.
+    pub fn remote(mut self, remote: RemoteConfig) -> Self {
+        self.config.remote = remote;
+        self
+    }
+
+    /// Serves clients over TLS.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.config.tls = Some(tls);
+        self
+    }
+
+    /// Also serves local clients over a Unix domain socket.
+    #[cfg(unix)]
+    pub fn unix(mut self, unix: UnixConfig) -> Self {
+        self.config.unix = Some(unix);
+        self
+    }
+
+    /// Requires every client to authenticate.
+    pub fn auth(mut self, auth: AuthConfig) -> Self {
+        self.config.auth = Some(auth);
+        self
+    }
+
+    /// How the binary reports events.
+    pub fn log(mut self, log: LogConfig) -> Self {
+        self.config.log = log;
+        self
+    }
+
+    /// Validates and returns the configuration.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::Invalid`] describing the first offending value.
+    pub fn build(self) -> Result<ServerConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 /// Maximum sized allowed for the packets of the protocol
 /// 
 /// This constant defines the max size a packet should have.
@@ -10,4 +12,86 @@ pub const MAX_PACKET_SIZE: usize = 1024 * 1024;
 /// This constant defines the minimum size a packet should have.
 /// 4 bytes is the size of the header therefor no packet shorter
 /// can be processed properly. It is used in [`PacketSize::from_slice`]
-pub const MIN_PACKET_SIZE: usize = 4;
\ No newline at end of file
+pub const MIN_PACKET_SIZE: usize = 4;
+/// Version of the wire protocol spoken by this build
+///
+/// This constant is exchanged in the `Hello`/`Welcome` handshake. It must be
+/// bumped whenever the layout of an existing message (or of an enum such as
+/// [`crate::HashAlgorithms`]) changes, so that peers built against another
+/// layout are refused instead of silently decoding the wrong variant.
+pub const PROTOCOL_VERSION: u16 = 16;
+
+/// Maximum number of requests a single connection may have in flight
+///
+/// Clients may pipeline requests without waiting for their responses. Once
+/// this many are pending, the server stops reading from that connection
+/// until a response has been written back, which bounds the memory a single
+/// client can pin.
+pub const MAX_IN_FLIGHT_REQUESTS: usize = 256;
+
+/// Number of heartbeat and progress frames a connection may have waiting to
+/// be written
+///
+/// Pings, pongs and progress frames have their own outbound queue, separate
+/// from the task responses. Frames beyond this many are dropped rather than
+/// queued, so a peer flooding pings or reading slowly cannot hold up the
+/// workers.
+pub const CONTROL_QUEUE_DEPTH: usize = 16;
+
+/// Time a worker waits for room in the outbound queue of a connection
+///
+/// The queue has room for every response a connection may have in flight,
+/// so workers should never wait for it. Should one still wait this long, the
+/// connection is closed and the worker moves on to the next task.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default maximum number of connections a server serves at once
+///
+/// Every connection holds a task, two socket buffers and an outbound queue.
+/// Past this many, new clients are refused instead of letting a connection
+/// flood exhaust the file descriptors or the memory of the orchestrator.
+pub const MAX_CONNECTIONS: usize = 1024;
+
+/// Maximum number of refused connections told why at once
+///
+/// Refusing a connection takes a task, possibly a TLS handshake and a short
+/// drain of the socket. Past this many refusals in progress, further
+/// connections over a limit are closed without an error frame, so that a
+/// flood cannot pin more file descriptors than the limits allow.
+pub const MAX_CONCURRENT_REFUSALS: usize = 32;
+
+/// Time allowed to tell a refused connection why, handshake included
+pub const REFUSAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time the server waits before accepting again after a transient failure
+///
+/// Accepting fails while the process is out of file descriptors. Retrying
+/// at once would spin, and stopping would take the server down under a
+/// connection flood, so new connections are left pending for a moment while
+/// existing ones finish.
+pub const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Maximum output length, in bytes, that may be requested from an XOF
+///
+/// Extendable-output functions such as SHAKE128 can produce arbitrarily long
+/// digests. The hex-encoded result must fit in a single response frame, so the
+/// requested length is capped well below [`MAX_PACKET_SIZE`].
+pub const MAX_XOF_OUTPUT_LEN: u32 = 64 * 1024;
+
+/// Default size of the buffer used to stream files into the hashers
+///
+/// 8KB keeps memory usage per worker minimal. Servers hashing large files
+/// from fast storage may raise it through their configuration.
+pub const DEFAULT_HASH_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Length, in bytes, of the random nonce of an authentication challenge
+///
+/// 256 bits make it practically impossible for a nonce to repeat, so an
+/// answer captured on one connection cannot be replayed on another.
+pub const AUTH_NONCE_LEN: usize = 32;
+
+/// Minimum length, in bytes, of a shared authentication key
+///
+/// Shorter keys could be brute-forced from a single captured
+/// challenge-response exchange.
+pub const MIN_AUTH_KEY_LEN: usize = 16;
@@ -1,7 +1,9 @@
-use crate::FilePath;
-use digest::Digest;
+use crate::{
+    FilePath, HashAlgorithms, protocol::FailureReason, remote::RemoteFetcher, sandbox::Sandbox,
+};
+use digest::{Digest, ExtendableOutput, Update, XofReader};
 use std::{
-    fmt, fs,
+    fmt::{self, Write},
     io::{self, Read},
 };
 
@@ -9,71 +11,223 @@ use std::{
 
 /// Represents failures encountered during the file hashing process.
 ///
-/// This error type is returned by [`hash_reader`]. It distinguishes between configuration gaps (unimplemented features)
-/// and environmental issues (filesystem permissions).
+/// This error type is returned by [`open`] and [`hash_reader`]. It distinguishes between
+/// configuration gaps (disabled features), invalid requests and environmental issues
+/// (filesystem permissions, unreachable servers).
 #[derive(Debug, thiserror::Error)]
 pub enum HashError {
-    /// Indicates an attempt to use a feature that is defined but not yet functional.
+    /// Indicates an attempt to use a feature that is disabled on this server.
     ///
-    /// Currently, this is returned when a [`FilePath::Remote`] variant is passed 
-    /// to the hasher. Remote file streaming is planned for a future release.
-    #[error("Remote hashing is not yet implemented")]
-    NotImplemented,
+    /// This is returned when a [`FilePath::Remote`] variant is passed to the
+    /// hasher while no remote host is allowed by the [`crate::remote::RemoteConfig`].
+    #[error("Remote hashing is disabled on this server")]
+    RemoteDisabled,
 
     /// Encapsulates failures at the OS or filesystem level.
     ///
-    /// This variant is commonly triggered if the file at the provided path 
-    /// does not exist, the process lacks read permissions, or the disk 
+    /// This variant is commonly triggered if the file at the provided path
+    /// does not exist, the process lacks read permissions, or the disk
     /// encounters a hardware failure during streaming.
     ///
     /// # Diagnostic Note
-    /// The underlying [`std::io::Error`] provides specific OS error codes 
+    /// The underlying [`std::io::Error`] provides specific OS error codes
     /// (e.g., `PermissionDenied` or `NotFound`) to aid in debugging.
     #[error("IO Error: {0}")]
     Io(#[from] io::Error),
+
+    /// The requested algorithm exists in the protocol but has no
+    /// implementation on this server.
+    #[error("Hash algorithm {0:?} is not implemented")]
+    UnsupportedAlgorithm(HashAlgorithms),
+
+    /// The requested output length is not valid for the algorithm.
+    ///
+    /// Only extendable-output functions accept a caller-chosen length, and
+    /// it must lie between 1 and [`crate::constants::MAX_XOF_OUTPUT_LEN`].
+    #[error("Invalid output length: {0:?}")]
+    InvalidOutputLength(Option<u32>),
+
+    /// The remote URL is malformed or uses an unsupported scheme.
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+
+    /// The remote URL, or one of its redirects, points to a host that is
+    /// not in the allowlist.
+    #[error("Host {0} is not allowed")]
+    HostNotAllowed(String),
+
+    /// The remote server answered with a non-success HTTP status.
+    #[error("Remote server answered with status {0}")]
+    RemoteStatus(u16),
+
+    /// The local path is refused by the server's [`Sandbox`], either because
+    /// it resolves outside the allowed roots or because it is not a regular file.
+    #[error("Path not allowed: {0}")]
+    PathNotAllowed(String),
+
+    /// The input is larger than the configured limit, in bytes.
+    #[error("Input exceeds the limit of {0} bytes")]
+    TooLarge(u64),
+
+    /// The HTTP transfer failed (DNS, connection, TLS, timeout...).
+    #[error("HTTP Error: {0}")]
+    Http(#[from] ureq::Error),
+}
+
+impl HashError {
+    /// Maps the error onto the [`FailureReason`] reported to clients.
+    pub fn reason(&self) -> FailureReason {
+        match self {
+            HashError::RemoteDisabled => FailureReason::RemoteDisabled,
+            HashError::UnsupportedAlgorithm(_) => FailureReason::UnsupportedAlgorithm,
+            HashError::InvalidOutputLength(_) | HashError::InvalidUrl(_) => {
+                FailureReason::InvalidRequest
+            }
+            HashError::HostNotAllowed(_) => FailureReason::RemoteNotAllowed,
+            HashError::RemoteStatus(404 | 410) => FailureReason::NotFound,
+            HashError::RemoteStatus(401 | 403) => FailureReason::PermissionDenied,
+            HashError::RemoteStatus(_) => FailureReason::RemoteUnavailable,
+            HashError::TooLarge(_) => FailureReason::TooLarge,
+            HashError::PathNotAllowed(_) => FailureReason::PathNotAllowed,
+            HashError::Http(ureq::Error::Timeout(_)) => FailureReason::Timeout,
+            HashError::Http(_) => FailureReason::RemoteUnavailable,
+            HashError::Io(e) => match e.kind() {
+                io::ErrorKind::NotFound => FailureReason::NotFound,
+                io::ErrorKind::PermissionDenied => FailureReason::PermissionDenied,
+                io::ErrorKind::TimedOut => FailureReason::Timeout,
+                io::ErrorKind::FileTooLarge => FailureReason::TooLarge,
+                _ => FailureReason::Io,
+            },
+        }
+    }
 }
 
-/// Computes the hash of a given file
-/// 
+/// An opened input, ready to be hashed.
+pub struct Source {
+    /// The bytes to hash.
+    pub reader: Box<dyn Read + Send>,
+    /// Size of the input, when known before reading it.
+    pub len: Option<u64>,
+}
+
+/// Opens the byte source designated by a [`FilePath`]
+///
+/// Local paths are opened from the filesystem once the [`Sandbox`] allowed
+/// them, while remote URLs are streamed through the given [`RemoteFetcher`],
+/// which enforces the host allowlist, redirect policy, timeouts and size limit.
+///
+/// # Error
+/// - Any error of [`Sandbox::open`] for a [`FilePath::Local`]
+/// - Any error of [`RemoteFetcher::open`] for a [`FilePath::Remote`]
+pub fn open(
+    path: &FilePath,
+    sandbox: &Sandbox,
+    remote: &RemoteFetcher,
+) -> Result<Source, HashError> {
+    match path {
+        FilePath::Local(p) => {
+            let file = sandbox.open(p)?;
+            let metadata = file.metadata()?;
+            Ok(Source {
+                len: metadata.is_file().then_some(metadata.len()),
+                reader: Box::new(file),
+            })
+        }
+        FilePath::Remote(url) => remote.open(url),
+    }
+}
+
+/// Computes the hash of a given reader
+///
 /// This function is generic over any type that implements the [`Digest`] trait,
-/// allowing support for all of the sha2 exposed hashing algorithms. It
-/// uses a buffer of 8KB to minimize memory usage.
-/// 
+/// allowing support for all of the sha2 exposed hashing algorithms. The source
+/// is streamed through a buffer of `buffer_size` bytes (see
+/// [`crate::constants::DEFAULT_HASH_BUFFER_SIZE`]) to bound memory usage.
+///
 /// # Error
-/// - [`HashError::Io``]: Returned if the file couldn't be read or opened
-/// - [`HashError::NotImplemented`]: Returned if the file path is a [`Remote`] which is not implemented yet
+/// - [`HashError::Io`]: Returned if the source couldn't be read
 /// # Exemples
 /// ```
 /// use sha2::Sha256;
-/// use task_scheduler::{
-///     crypto::hash_reader,
-///     FilePath,
-/// };
-/// 
-/// let path = FilePath::Local(String::from("/tmp/test.txt"));
-/// let result = hash_reader::<Sha256>(&path);
+/// use task_scheduler::{constants::DEFAULT_HASH_BUFFER_SIZE, crypto::hash_reader};
+///
+/// let mut src: &[u8] = b"hello";
+/// let result = hash_reader::<Sha256>(&mut src, DEFAULT_HASH_BUFFER_SIZE);
 /// ```
-pub fn hash_reader<D>(path: &FilePath) -> Result<String, HashError>
+pub fn hash_reader<D>(src: &mut dyn Read, buffer_size: usize) -> Result<String, HashError>
 where
     D: Digest,
     digest::Output<D>: fmt::LowerHex,
 {
-    let mut src = match path {
-        FilePath::Local(p) => fs::File::open(p)?,
-        FilePath::Remote(_) => return Err(HashError::NotImplemented),
-    };
-
     let mut hasher = D::new();
-    let mut buffer = [0u8; 8192];
+    pump(src, buffer_size, |chunk| Digest::update(&mut hasher, chunk))?;
+
+    let result = hasher.finalize();
+    Ok(format!("{:x}", result))
+}
+
+/// Computes the output of an extendable-output function (XOF) over a given reader
+///
+/// This is the XOF counterpart of [`hash_reader`]: functions such as SHAKE128
+/// do not have a fixed digest size, so the caller chooses how many bytes of
+/// output to squeeze. The source is streamed through a `buffer_size` buffer.
+///
+/// # Error
+/// - [`HashError::Io`]: Returned if the source couldn't be read
+/// # Exemples
+/// ```
+/// use sha3::Shake128;
+/// use task_scheduler::{constants::DEFAULT_HASH_BUFFER_SIZE, crypto::hash_xof_reader};
+///
+/// let mut src: &[u8] = b"hello";
+/// let result = hash_xof_reader::<Shake128>(&mut src, 32, DEFAULT_HASH_BUFFER_SIZE);
+/// ```
+pub fn hash_xof_reader<X>(
+    src: &mut dyn Read,
+    output_len: usize,
+    buffer_size: usize,
+) -> Result<String, HashError>
+where
+    X: Default + Update + ExtendableOutput,
+{
+    let mut hasher = X::default();
+    pump(src, buffer_size, |chunk| hasher.update(chunk))?;
+
+    let mut output = vec![0u8; output_len];
+    hasher.finalize_xof().read(&mut output);
+
+    let mut hex = String::with_capacity(output_len * 2);
+    for byte in output {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    Ok(hex)
+}
+
+/// Computes the BLAKE3 hash of a given reader
+///
+/// BLAKE3 does not implement the [`Digest`] trait without extra features,
+/// so it gets its own entry point sharing the same streaming loop.
+///
+/// # Error
+/// - [`HashError::Io`]: Returned if the source couldn't be read
+pub fn hash_blake3(src: &mut dyn Read, buffer_size: usize) -> Result<String, HashError> {
+    let mut hasher = blake3::Hasher::new();
+    pump(src, buffer_size, |chunk| {
+        hasher.update(chunk);
+    })?;
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Streams `src` to its end, handing every chunk read to `update`.
+fn pump(src: &mut dyn Read, buffer_size: usize, mut update: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut buffer = vec![0u8; buffer_size.max(1)];
 
     loop {
         let count = src.read(&mut buffer)?;
         if count == 0 {
-            break;
+            return Ok(());
         }
-        hasher.update(&buffer[..count]);
+        update(&buffer[..count]);
     }
-
-    let result = hasher.finalize();
-    Ok(format!("{:x}", result))
 }
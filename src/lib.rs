@@ -18,22 +18,28 @@
 //! The project is divided into three main pillars:
 //! 2. [`protocol`]: Defines the data structures and enums shared by client and server as well as the packet logic.
 //! 3. [`workers`]: Contains the logic to dispatch and execute tasks.
+//! 4. [`server`]: Accepts connections and feeds their requests to the workers.
 
 
-use crate::workers::start_worker_pool;
 use serde::{Deserialize, Serialize};
-use std::sync::{
-    Arc,
-    atomic::{AtomicU64, AtomicUsize, Ordering},
-};
-use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpListener},
-    sync::{mpsc, oneshot},
-};
+use std::{fmt, str::FromStr};
 
 
 
+/// Module handling client authentication
+///
+/// This module defines the shared keys of the server and the client
+/// credentials answering its HMAC challenge.
+pub mod auth;
+/// Module providing an asynchronous client for the protocol
+///
+/// This module defines a pooled, pipelining [`client::Client`] so that
+/// consumers do not have to hand-roll the framing and handshake.
+pub mod client;
+/// Module holding the server configuration
+///
+/// This module defines [`config::ServerConfig`] and its TOML loading.
+pub mod config;
 /// Global constants used in the protocol
 /// 
 /// This module defines the constants used in the protocol such as [`MAX_PACKET_SIZE`]
@@ -43,40 +49,62 @@ pub mod constants;
 /// This modules defines function related to encryption as well as 
 /// §any tasks around crypto.
 pub mod crypto;
+/// Module exposing the server metrics
+///
+/// This module defines [`metrics::ServerMetrics`] and the HTTP endpoint
+/// serving them in the Prometheus text format.
+pub mod metrics;
 /// Module to centralize all of the protocol logic
 /// 
 /// This module defines any function or structure related to the network 
 /// protocol.
 pub mod protocol;
+/// Module holding the queue in front of the workers
+///
+/// This module defines the bounded [`queue::WorkQueue`] and the admission
+/// policies applied once it is full.
+pub mod queue;
+/// Module enforcing per-client quotas
+///
+/// This module defines the [`quota::QuotaConfig`] tiers and the token
+/// buckets and in-flight counts kept for every client.
+pub mod quota;
+/// Module to fetch remote files
+///
+/// This module defines the HTTP(S) client used to stream
+/// [`FilePath::Remote`] targets into the hashers.
+pub mod remote;
+/// Module restricting the local files clients may hash
+///
+/// This module defines the [`sandbox::Sandbox`] policy that canonicalizes
+/// requested paths and checks them against the allowed roots.
+pub mod sandbox;
+/// Module that accepts client connections
+///
+/// This module defines the listener loop, the handshake and the
+/// per-connection handler that feeds the worker pool.
+pub mod server;
+/// Module providing the TLS transport
+///
+/// This module defines the server and client TLS settings, built on rustls,
+/// including optional mutual authentication.
+pub mod tls;
+/// Module providing the Unix domain socket transport
+///
+/// This module defines the settings of the local listener, including the
+/// permissions of its socket file and the allowed peer credentials.
+#[cfg(unix)]
+pub mod unix;
 /// Module that handles the dispatching of tasks
 /// 
 /// This module defines the functions and helpers that do the actual
 /// task dispatch.
 pub mod workers;
 
+pub use metrics::ServerMetrics;
+pub use server::{ShutdownSummary, run_server, run_server_on};
+pub use tokio_util::sync::CancellationToken;
 
-/// Thread-safe metrics for monitoring the orchestrator's state.
-///
-/// This structure uses atomic integers to allow high-concurrency updates
-/// without the overhead of locking. It is typically wrapped in an [`std::sync::Arc`]
-/// and shared between the listener loop and worker tasks.
-pub struct ServerMetrics {
-    /// The total number of tasks successfully processed since the server started.
-    pub processed_tasks: AtomicU64,
-    /// The number of clients currently connected to the orchestrator.
-    pub active_connections: AtomicU64,
-}
-
-impl ServerMetrics {
-    /// Creates a new instance of [`ServerMetrics`] with all counters initialized to zero.
-    #[inline]
-    pub fn new() -> Self {
-        Self {
-            processed_tasks: AtomicU64::new(0),
-            active_connections: AtomicU64::new(0),
-        }
-    }
-}
 
 /// Supported hash algorithms used by the protocol for integrity checks
 /// and selection based on client/server capabilities.
@@ -101,8 +129,101 @@ pub enum HashAlgorithms {
 
     UNIMPLEMENTED,
 }
+
+impl HashAlgorithms {
+    /// Every algorithm the worker pool is able to compute.
+    ///
+    /// This list is advertised to clients in the [`protocol::Welcome`] frame.
+    pub const IMPLEMENTED: &'static [HashAlgorithms] = &[
+        HashAlgorithms::SHA224,
+        HashAlgorithms::SHA256,
+        HashAlgorithms::SHA384,
+        HashAlgorithms::SHA512,
+        HashAlgorithms::SHA512_224,
+        HashAlgorithms::SHA512_256,
+        HashAlgorithms::SHA3_224,
+        HashAlgorithms::SHA3_256,
+        HashAlgorithms::SHA3_384,
+        HashAlgorithms::SHA3_512,
+        HashAlgorithms::SHAKE128,
+        HashAlgorithms::SHAKE256,
+        HashAlgorithms::BLAKE3,
+    ];
+
+    /// Returns `true` if the worker pool can compute this algorithm.
+    #[inline]
+    pub fn is_implemented(self) -> bool {
+        Self::IMPLEMENTED.contains(&self)
+    }
+
+    /// Returns `true` for extendable-output functions, whose output length
+    /// is chosen by the caller.
+    #[inline]
+    pub fn is_xof(self) -> bool {
+        matches!(self, HashAlgorithms::SHAKE128 | HashAlgorithms::SHAKE256)
+    }
+
+    /// Returns the canonical lower-case name of the algorithm (e.g. `sha3-256`).
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithms::SHA224 => "sha224",
+            HashAlgorithms::SHA256 => "sha256",
+            HashAlgorithms::SHA384 => "sha384",
+            HashAlgorithms::SHA512 => "sha512",
+            HashAlgorithms::SHA512_224 => "sha512-224",
+            HashAlgorithms::SHA512_256 => "sha512-256",
+            HashAlgorithms::SHA3_224 => "sha3-224",
+            HashAlgorithms::SHA3_256 => "sha3-256",
+            HashAlgorithms::SHA3_384 => "sha3-384",
+            HashAlgorithms::SHA3_512 => "sha3-512",
+            HashAlgorithms::SHAKE128 => "shake128",
+            HashAlgorithms::SHAKE256 => "shake256",
+            HashAlgorithms::BLAKE3 => "blake3",
+            HashAlgorithms::UNIMPLEMENTED => "unimplemented",
+        }
+    }
+
+    /// Output length, in bytes, used for an XOF when the request leaves it unset.
+    ///
+    /// This matches the full security strength of each function (twice its
+    /// capacity parameter). Returns `None` for fixed-size algorithms.
+    #[inline]
+    pub fn default_output_len(self) -> Option<u32> {
+        match self {
+            HashAlgorithms::SHAKE128 => Some(32),
+            HashAlgorithms::SHAKE256 => Some(64),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for HashAlgorithms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned when parsing an unknown algorithm name.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown hash algorithm: {0}")]
+pub struct ParseAlgorithmError(String);
+
+impl FromStr for HashAlgorithms {
+    type Err = ParseAlgorithmError;
+
+    /// Parses a name as returned by [`HashAlgorithms::name`], ignoring case and
+    /// accepting `_` in place of `-`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.trim().to_ascii_lowercase().replace('_', "-");
+        Self::IMPLEMENTED
+            .iter()
+            .copied()
+            .find(|algo| algo.name() == wanted)
+            .ok_or_else(|| ParseAlgorithmError(s.to_owned()))
+    }
+}
+
 /// Represents a path to a resource in the system or remotely fetched.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum FilePath {
     /// Local is used for files on the computer
     Local(String),
@@ -110,97 +231,11 @@ pub enum FilePath {
     Remote(String),
 }
 
-use crate::protocol::{read_protocol, ProtocolMessage, TaskRequest};
-use crate::workers::WorkItem;
-
-
-/// Start the server loop on an existing TCP listener.
-///
-/// This function accepts incoming connections, updates connection metrics, and delegates
-/// work items to a worker pool. It returns when the underlying I/O fails or the connection
-/// is closed.
-pub async fn run_server_on(listener: TcpListener, num_workers: usize) -> tokio::io::Result<()> {
-    let metrics = Arc::new(ServerMetrics::new());
-    let (tx, rx) = mpsc::channel::<WorkItem>(100);
-
-    start_worker_pool(rx, num_workers, Arc::clone(&metrics)).await;
-
-    loop {
-        let (mut socket, addr) = listener.accept().await?;
-        let task_sender = tx.clone();
-        let conn_metrics = Arc::clone(&metrics);
-        tokio::spawn(async move {
-            conn_metrics
-                .active_connections
-                .fetch_add(1, Ordering::SeqCst);
-            println!(
-                "Client {} connected. Active: {}",
-                addr,
-                conn_metrics.active_connections.load(Ordering::SeqCst)
-            );
-
-            loop {
-                let packet = match read_protocol(&mut socket).await {
-                    Ok(p) => p,
-                    Err(e) => {
-                        println!("Protocol read error from {}: {:?}", addr, e);
-                        break;
-                    }
-                };
-                let task = match packet {
-                    ProtocolMessage::TaskRequest(t) => t,
-                    ProtocolMessage::TaskResponse(_) => continue,
-                };
-                match task {
-                    TaskRequest::HashPacket(p) => {
-                        let (resp_tx, resp_rx) = oneshot::channel();
-                        let work = WorkItem::new(p, resp_tx);
-
-                        let _ = task_sender.send(work).await;
-
-                        if let Ok(result) = resp_rx.await {
-                            let packet = match result.into_packet() {
-                                Ok(p) => p,
-                                Err(_) => {
-                                    println!("Invalid response from worker");
-                                    continue;
-                                }
-                            };
-
-                            match socket.write_all(&packet).await {
-                                Ok(()) => {}
-                                Err(e) => {
-                                    println!("Failed to write to the socket: {}", e);
-                                    break;
-                                }
-                            }
-
-                            let total = conn_metrics.processed_tasks.load(Ordering::SeqCst);
-                            let active = conn_metrics.active_connections.load(Ordering::SeqCst);
-                            println!(
-                                "Task Complete. Total Processed: {}, Active Now: {}",
-                                total, active
-                            );
-                        }
-                    }
-                }
-            }
-
-            conn_metrics
-                .active_connections
-                .fetch_sub(1, Ordering::SeqCst);
-            println!("Client {} disconnected", addr);
-        });
+impl fmt::Display for FilePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilePath::Local(path) => f.write_str(path),
+            FilePath::Remote(url) => f.write_str(url),
+        }
     }
 }
-
-/// Bind to the given address and start the server with a worker pool.
-///
-/// This function creates a TCP listener on the provided address and delegates all
-/// incoming work to the worker pool managed by [`run_server_on`].
-pub async fn run_server(addr: &str, num_workers: usize) -> tokio::io::Result<()> {
-    let listener = TcpListener::bind(addr).await?;
-    println!("Server listening on {}", addr);
-    run_server_on(listener, num_workers).await
-}
-
@@ -1,86 +1,368 @@
-use std::sync::{Arc, atomic::Ordering};
+use clap::{Args, Parser, Subcommand};
+use tracing_subscriber::EnvFilter;
+use std::{path::PathBuf, process::ExitCode, time::Duration};
 use task_scheduler::{
-    ServerMetrics,
-    protocol::{ProtocolMessage, TaskRequest, read_protocol},
-    workers::{WorkItem, start_worker_pool},
-};
-use tokio::{
-    io::AsyncWriteExt,
-    net::TcpListener,
-    sync::{mpsc, oneshot},
+    CancellationToken, FilePath, HashAlgorithms,
+    auth::Credentials,
+    client::Client,
+    config::{ConfigError, LogConfig, LogFormat, ServerConfig},
+    protocol::{HashingPacket, Priority, Progress},
+    run_server,
+    tls::{ClientTls, TlsConfig},
 };
+#[cfg(unix)]
+use task_scheduler::unix::UnixConfig;
+
+/// Orchestrator distributing file hashing tasks to a pool of workers.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the orchestrator server.
+    Serve(ServeArgs),
+    /// Hash one or more files through a running server.
+    Submit(SubmitArgs),
+    /// Check that a file matches an expected digest.
+    Verify(VerifyArgs),
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// TOML configuration file; command line flags take precedence over it.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Address to listen on.
+    #[arg(long)]
+    bind: Option<String>,
+    /// Number of concurrent workers.
+    #[arg(long)]
+    workers: Option<usize>,
+    /// Number of tasks that may wait for a free worker.
+    #[arg(long)]
+    queue_depth: Option<usize>,
+    /// Address of the Prometheus metrics endpoint.
+    #[arg(long)]
+    metrics_bind: Option<String>,
+    /// Log filter, e.g. `debug` or `info,task_scheduler::workers=debug`.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Log output format: `text` or `json`.
+    #[arg(long)]
+    log_format: Option<LogFormat>,
+    /// PEM certificate chain; enables TLS.
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of the TLS certificate.
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// PEM CA certificates; requires clients to present a certificate it signed.
+    #[arg(long, requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+    /// Directory clients may hash files from; repeat to allow several.
+    #[arg(long = "sandbox-root", value_name = "DIR")]
+    sandbox_roots: Vec<PathBuf>,
+    /// Also serve local clients on this Unix domain socket.
+    #[cfg(unix)]
+    #[arg(long)]
+    unix_socket: Option<PathBuf>,
+    /// Do not listen on TCP; requires a Unix socket.
+    #[arg(long)]
+    no_tcp: bool,
+}
+
+#[derive(Debug, Args)]
+struct ClientArgs {
+    /// Address of the orchestrator, or `unix:PATH` for a local socket.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    server: String,
+    /// Hash algorithm, e.g. sha256, sha3-512, shake128 or blake3.
+    #[arg(long, short, default_value = "sha256")]
+    algorithm: HashAlgorithms,
+    /// Output length in bytes for extendable-output functions (SHAKE).
+    #[arg(long)]
+    output_len: Option<u32>,
+    /// Seconds to wait for each result.
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+    /// How urgently the server should run the tasks: interactive, normal or bulk.
+    #[arg(long, default_value = "normal")]
+    priority: Priority,
+    /// PEM CA certificates to trust; enables TLS.
+    #[arg(long)]
+    tls_ca: Option<PathBuf>,
+    /// Name checked against the server certificate, if not the host of --server.
+    #[arg(long, requires = "tls_ca")]
+    tls_server_name: Option<String>,
+    /// PEM client certificate chain, for servers requiring mutual TLS.
+    #[arg(long, requires_all = ["tls_ca", "tls_key"])]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of the client certificate.
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Identity to authenticate as, for servers requiring authentication.
+    #[arg(long, requires = "key_file")]
+    identity: Option<String>,
+    /// File holding the shared key of --identity.
+    #[arg(long, requires = "identity")]
+    key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct SubmitArgs {
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Paths, as seen by the server, or http(s) URLs to hash.
+    #[arg(required = true)]
+    paths: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Expected hex-encoded digest.
+    #[arg(long)]
+    expected: String,
+    /// Print the progress of the task on stderr while it runs.
+    #[arg(long)]
+    progress: bool,
+    /// Path, as seen by the server, or http(s) URL to check.
+    path: String,
+}
 
 #[tokio::main]
-async fn main() -> tokio::io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:8080").await?;
-    println!("Server listening on 127.0.0.1:8080");
-    let metrics = Arc::new(ServerMetrics::new());
-    let (tx, rx) = mpsc::channel::<WorkItem>(100);
-
-    start_worker_pool(rx, 10, Arc::clone(&metrics)).await;
-
-    loop {
-        let (mut socket, addr) = listener.accept().await?;
-        let task_sender = tx.clone();
-        let conn_metrics = Arc::clone(&metrics);
-        tokio::spawn(async move {
-            conn_metrics
-                .active_connections
-                .fetch_add(1, Ordering::SeqCst);
-            println!(
-                "Client {} connected. Active: {}",
-                addr,
-                conn_metrics.active_connections.load(Ordering::SeqCst)
+async fn main() -> ExitCode {
+    match Cli::parse().command {
+        Command::Serve(args) => serve(args).await,
+        Command::Submit(args) => submit(args).await,
+        Command::Verify(args) => verify(args).await,
+    }
+}
+
+async fn serve(args: ServeArgs) -> ExitCode {
+    let mut config = match &args.config {
+        Some(path) => match ServerConfig::from_file(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return ExitCode::from(2);
+            }
+        },
+        None => ServerConfig::default(),
+    };
+    if let Some(bind) = args.bind {
+        config.bind = bind;
+    }
+    if let Some(workers) = args.workers {
+        config.workers = workers;
+    }
+    if let Some(queue_depth) = args.queue_depth {
+        config.queue_depth = queue_depth;
+    }
+    if let Some(metrics_bind) = args.metrics_bind {
+        config.metrics_bind = Some(metrics_bind);
+    }
+    if let Some(level) = args.log_level {
+        config.log.level = level;
+    }
+    if let Some(format) = args.log_format {
+        config.log.format = format;
+    }
+    if let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) {
+        let client_ca = args
+            .tls_client_ca
+            .or_else(|| config.tls.take().and_then(|tls| tls.client_ca));
+        config.tls = Some(TlsConfig { cert, key, client_ca });
+    }
+    if !args.sandbox_roots.is_empty() {
+        config.sandbox.roots = args.sandbox_roots;
+    }
+    #[cfg(unix)]
+    if let Some(path) = args.unix_socket {
+        match &mut config.unix {
+            Some(unix) => unix.path = path,
+            None => config.unix = Some(UnixConfig::new(path)),
+        }
+    }
+    if args.no_tcp {
+        config.tcp = false;
+    }
+    if let Err(e) = config.validate() {
+        eprintln!("{}", e);
+        return ExitCode::from(2);
+    }
+
+    init_logging(&config.log);
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn(cancel_on_signal(shutdown.clone()));
+
+    match run_server(config, shutdown).await {
+        Ok(summary) => {
+            tracing::info!(
+                elapsed_ms = summary.elapsed.as_millis() as u64,
+                completed_tasks = summary.completed_tasks,
+                abandoned_tasks = summary.abandoned_tasks,
+                connections = summary.connections,
+                "shutdown complete"
             );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "server error");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Installs the global subscriber; `RUST_LOG` overrides the configured level.
+fn init_logging(log: &LogConfig) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&log.level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match log.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// Cancels `shutdown` on the first SIGINT (Ctrl-C) or SIGTERM.
+async fn cancel_on_signal(shutdown: CancellationToken) {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutdown requested");
+    shutdown.cancel();
+}
+
+async fn submit(args: SubmitArgs) -> ExitCode {
+    let client = match connect(&args.client) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let packets = args
+        .paths
+        .iter()
+        .map(|path| packet(&args.client, path))
+        .collect();
 
-            loop {
-                let packet = match read_protocol(&mut socket).await {
-                    Ok(p) => p,
-                    Err(_) => break,
-                };
-                let task = match packet {
-                    ProtocolMessage::TaskRequest(t) => t,
-                    ProtocolMessage::TaskResponse(_) => continue,
-                };
-                match task {
-                    TaskRequest::HashPacket(p) => {
-                        let (resp_tx, resp_rx) = oneshot::channel();
-                        let work = WorkItem::new(p, resp_tx);
-
-                        let _ = task_sender.send(work).await;
-
-                        if let Ok(result) = resp_rx.await {
-                            let packet = match result.into_packet() {
-                                Ok(p) => p,
-                                Err(_) => {
-                                    println!("Invalid response from worker");
-                                    continue;
-                                }
-                            };
-
-                            match socket.write_all(&packet).await {
-                                Ok(()) => {}
-                                Err(e) => {
-                                    println!("Failed to write to the socket: {}", e);
-                                    break;
-                                }
-                            }
-
-                            let total = conn_metrics.processed_tasks.load(Ordering::SeqCst);
-                            let active = conn_metrics.active_connections.load(Ordering::SeqCst);
-                            println!(
-                                "Task Complete. Total Processed: {}, Active Now: {}",
-                                total, active
-                            );
-                        }
-                    }
-                }
+    let mut status = ExitCode::SUCCESS;
+    for (result, path) in client.hash_batch(packets).await.into_iter().zip(&args.paths) {
+        match result {
+            Ok(digest) => println!("{}  {}", digest, path),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                status = ExitCode::FAILURE;
             }
+        }
+    }
+    status
+}
+
+async fn verify(args: VerifyArgs) -> ExitCode {
+    let client = match connect(&args.client) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let deadline = Duration::from_secs(args.client.timeout);
+    let packet = packet(&args.client, &args.path);
+    let result = if args.progress {
+        client
+            .submit_with_progress(packet, deadline, |p| eprintln!("{}: {}", args.path, describe(p)))
+            .await
+    } else {
+        client.submit(packet, deadline).await
+    };
+    match result {
+        Ok(digest) if digest.eq_ignore_ascii_case(args.expected.trim()) => {
+            println!("{}: OK", args.path);
+            ExitCode::SUCCESS
+        }
+        Ok(digest) => {
+            println!("{}: FAILED (got {})", args.path, digest);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}: {}", args.path, e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn connect(args: &ClientArgs) -> Result<Client, ConfigError> {
+    let mut builder = Client::builder(&args.server)
+        .request_timeout(Duration::from_secs(args.timeout))
+        .priority(args.priority);
+    if let Some(ca) = &args.tls_ca {
+        let mut tls = ClientTls::new(ca)?;
+        if let Some(name) = &args.tls_server_name {
+            tls = tls.with_server_name(name.clone())?;
+        }
+        if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+            tls = tls.with_identity(cert, key)?;
+        }
+        builder = builder.tls(tls);
+    }
+    if let (Some(identity), Some(key_file)) = (&args.identity, &args.key_file) {
+        let key = std::fs::read_to_string(key_file)?;
+        builder = builder.credentials(Credentials::new(identity.clone(), key.trim_end()));
+    }
+    Ok(builder.build())
+}
+
+/// Formats a progress report, e.g. `512 MiB of 2048 MiB (25%), 310 MiB/s`.
+fn describe(progress: &Progress) -> String {
+    const MIB: u64 = 1 << 20;
+    let done = progress.bytes_processed / MIB;
+    let speed = progress.bytes_per_second / MIB;
+    match progress.total_bytes {
+        Some(total) if total > 0 => format!(
+            "{} MiB of {} MiB ({}%), {} MiB/s",
+            done,
+            total / MIB,
+            progress.bytes_processed * 100 / total,
+            speed
+        ),
+        _ => format!("{} MiB, {} MiB/s", done, speed),
+    }
+}
+
+/// Builds the request for `path`, treating http(s) URLs as remote files.
+fn packet(args: &ClientArgs, path: &str) -> HashingPacket {
+    let path = if path.starts_with("http://") || path.starts_with("https://") {
+        FilePath::Remote(path.to_owned())
+    } else {
+        FilePath::Local(path.to_owned())
+    };
 
-            conn_metrics
-                .active_connections
-                .fetch_sub(1, Ordering::SeqCst);
-            println!("Client {} disconnected", addr);
-        });
+    HashingPacket {
+        algorithm: args.algorithm,
+        path,
+        output_len: args.output_len,
+        progress: false,
     }
 }
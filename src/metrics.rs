@@ -0,0 +1,328 @@
+use crate::{
+    HashAlgorithms,
+    protocol::{FailureReason, ProtocolError, QuotaError},
+};
+use std::{
+    fmt::Write as _,
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Upper bounds, in seconds, of the task latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 30.0, 120.0,
+];
+
+const ALGORITHM_SLOTS: usize = HashAlgorithms::UNIMPLEMENTED as usize + 1;
+
+/// Thread-safe metrics for monitoring the orchestrator's state.
+///
+/// This structure uses atomic integers to allow high-concurrency updates
+/// without the overhead of locking. It is typically wrapped in an [`std::sync::Arc`]
+/// and shared between the listener loop and worker tasks.
+///
+/// [`ServerMetrics::render`] exports every value in the Prometheus text format.
+pub struct ServerMetrics {
+    /// The total number of tasks successfully processed since the server started.
+    pub processed_tasks: AtomicU64,
+    /// The number of clients currently connected to the orchestrator.
+    pub active_connections: AtomicU64,
+    /// The number of accepted tasks that are queued or being hashed.
+    pub pending_tasks: AtomicU64,
+    /// The number of accepted tasks waiting for a free worker.
+    pub queued_tasks: AtomicU64,
+    /// The total number of input bytes fed to the hashers.
+    pub bytes_hashed: AtomicU64,
+    /// The total number of tasks answered with
+    /// [`crate::protocol::TaskResponse::Busy`] because the queue was full.
+    pub busy_tasks: AtomicU64,
+    /// The total number of connections refused because the server was
+    /// serving [`crate::config::ServerConfig::max_connections`] already.
+    pub refused_connections: AtomicU64,
+    /// The total number of connections refused because their IP address had
+    /// [`crate::config::ServerConfig::max_connections_per_ip`] open already.
+    pub refused_peer_connections: AtomicU64,
+    tasks_by_algorithm: Vec<AtomicU64>,
+    failures_by_reason: Vec<AtomicU64>,
+    protocol_errors: Vec<AtomicU64>,
+    quota_rejections: Vec<AtomicU64>,
+    task_latency: Histogram,
+}
+
+impl ServerMetrics {
+    /// Creates a new instance of [`ServerMetrics`] with all counters initialized to zero.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            processed_tasks: AtomicU64::new(0),
+            active_connections: AtomicU64::new(0),
+            pending_tasks: AtomicU64::new(0),
+            queued_tasks: AtomicU64::new(0),
+            bytes_hashed: AtomicU64::new(0),
+            busy_tasks: AtomicU64::new(0),
+            refused_connections: AtomicU64::new(0),
+            refused_peer_connections: AtomicU64::new(0),
+            tasks_by_algorithm: counters(ALGORITHM_SLOTS),
+            failures_by_reason: counters(FailureReason::ALL.len()),
+            protocol_errors: counters(ProtocolError::KINDS.len()),
+            quota_rejections: counters(QuotaError::KINDS.len()),
+            task_latency: Histogram::new(),
+        }
+    }
+
+    /// Counts a task handed to a worker for `algorithm`.
+    pub fn record_task(&self, algorithm: HashAlgorithms) {
+        self.processed_tasks.fetch_add(1, Ordering::Relaxed);
+        self.tasks_by_algorithm[algorithm as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a task that ended in a [`crate::protocol::TaskResponse::Failed`].
+    pub fn record_failure(&self, reason: FailureReason) {
+        self.failures_by_reason[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the time between a task being accepted and its result being ready.
+    pub fn record_latency(&self, latency: Duration) {
+        self.task_latency.observe(latency);
+    }
+
+    /// Counts a connection-level protocol error.
+    ///
+    /// A peer closing its socket between two frames is a normal disconnection
+    /// and is not counted.
+    pub fn record_protocol_error(&self, error: &ProtocolError) {
+        if error.is_disconnect() {
+            return;
+        }
+        if let Some(index) = ProtocolError::KINDS.iter().position(|k| *k == error.kind()) {
+            self.protocol_errors[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a request answered with
+    /// [`crate::protocol::TaskResponse::QuotaExceeded`].
+    pub fn record_quota_rejection(&self, error: &QuotaError) {
+        if let Some(index) = QuotaError::KINDS.iter().position(|k| *k == error.kind()) {
+            self.quota_rejections[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of tasks executed for `algorithm`.
+    pub fn tasks_for(&self, algorithm: HashAlgorithms) -> u64 {
+        self.tasks_by_algorithm[algorithm as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of tasks that failed with `reason`.
+    pub fn failures_for(&self, reason: FailureReason) -> u64 {
+        self.failures_by_reason[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of protocol errors of the given [`ProtocolError::kind`].
+    pub fn protocol_errors_for(&self, kind: &str) -> u64 {
+        ProtocolError::KINDS
+            .iter()
+            .position(|k| *k == kind)
+            .map_or(0, |index| self.protocol_errors[index].load(Ordering::Relaxed))
+    }
+
+    /// Returns the number of requests refused for quotas of the given
+    /// [`QuotaError::kind`].
+    pub fn quota_rejections_for(&self, kind: &str) -> u64 {
+        QuotaError::KINDS
+            .iter()
+            .position(|k| *k == kind)
+            .map_or(0, |index| self.quota_rejections[index].load(Ordering::Relaxed))
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "tasks_total", "counter", "Tasks executed by the workers, by algorithm.");
+        let algorithms = HashAlgorithms::IMPLEMENTED
+            .iter()
+            .chain([&HashAlgorithms::UNIMPLEMENTED]);
+        for algo in algorithms {
+            let value = self.tasks_for(*algo);
+            let _ = writeln!(out, "task_scheduler_tasks_total{{algorithm=\"{}\"}} {}", algo, value);
+        }
+
+        header(&mut out, "task_failures_total", "counter", "Failed tasks, by reason.");
+        for reason in FailureReason::ALL {
+            let value = self.failures_for(*reason);
+            let _ = writeln!(
+                out,
+                "task_scheduler_task_failures_total{{reason=\"{}\"}} {}",
+                reason.name(),
+                value
+            );
+        }
+
+        header(&mut out, "protocol_errors_total", "counter", "Connection-level protocol errors, by kind.");
+        for kind in ProtocolError::KINDS {
+            let value = self.protocol_errors_for(kind);
+            let _ = writeln!(out, "task_scheduler_protocol_errors_total{{kind=\"{}\"}} {}", kind, value);
+        }
+
+        header(&mut out, "quota_rejections_total", "counter", "Requests refused for exceeding a client quota, by kind.");
+        for kind in QuotaError::KINDS {
+            let value = self.quota_rejections_for(kind);
+            let _ = writeln!(out, "task_scheduler_quota_rejections_total{{kind=\"{}\"}} {}", kind, value);
+        }
+
+        header(&mut out, "refused_connections_total", "counter", "Connections refused by a connection limit, by limit.");
+        let refusals = [
+            ("global", &self.refused_connections),
+            ("per_ip", &self.refused_peer_connections),
+        ];
+        for (limit, value) in refusals {
+            let _ = writeln!(
+                out,
+                "task_scheduler_refused_connections_total{{limit=\"{}\"}} {}",
+                limit,
+                value.load(Ordering::Relaxed)
+            );
+        }
+
+        let scalars = [
+            ("bytes_hashed_total", "counter", "Input bytes fed to the hashers.", &self.bytes_hashed),
+            ("busy_tasks_total", "counter", "Tasks turned away because the queue was full.", &self.busy_tasks),
+            ("active_connections", "gauge", "Clients currently connected.", &self.active_connections),
+            ("pending_tasks", "gauge", "Accepted tasks queued or being hashed.", &self.pending_tasks),
+            ("queue_depth", "gauge", "Accepted tasks waiting for a free worker.", &self.queued_tasks),
+        ];
+        for (name, kind, help, value) in scalars {
+            header(&mut out, name, kind, help);
+            let _ = writeln!(out, "task_scheduler_{} {}", name, value.load(Ordering::Relaxed));
+        }
+
+        header(
+            &mut out,
+            "task_latency_seconds",
+            "histogram",
+            "Time from accepting a task to its result being ready.",
+        );
+        self.task_latency.render(&mut out, "task_scheduler_task_latency_seconds");
+        out
+    }
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn counters(len: usize) -> Vec<AtomicU64> {
+    (0..len).map(|_| AtomicU64::new(0)).collect()
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP task_scheduler_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE task_scheduler_{} {}", name, kind);
+}
+
+/// A fixed-bucket latency histogram, updated without locking.
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Writes the cumulative buckets, sum and count of the histogram.
+    fn render(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// Serves `GET /metrics` over plain HTTP until `shutdown` is cancelled.
+///
+/// This is a deliberately small HTTP/1.1 responder meant for a Prometheus
+/// scraper: every connection answers a single request and is then closed.
+///
+/// # Errors
+/// Returns an error if accepting a connection fails.
+pub async fn serve_metrics(
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    shutdown: CancellationToken,
+) -> io::Result<()> {
+    loop {
+        let (socket, _) = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            accepted = listener.accept() => accepted?,
+        };
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let _ = timeout(Duration::from_secs(5), answer_scrape(socket, &metrics)).await;
+        });
+    }
+}
+
+/// Reads one HTTP request head and answers it.
+async fn answer_scrape(socket: TcpStream, metrics: &ServerMetrics) -> io::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    // Request heads are tiny; anything bigger is not a scraper.
+    let mut reader = BufReader::new(reader.take(8 * 1024));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut line = String::new();
+    while reader.read_line(&mut line).await? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), Some(_)) => ("404 Not Found", String::from("Not Found\n")),
+        _ => ("405 Method Not Allowed", String::from("Method Not Allowed\n")),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
@@ -1,11 +1,19 @@
-use crate::{FilePath, HashAlgorithms, constants::*};
+use crate::{FilePath, HashAlgorithms, auth::Credentials, constants::*};
 use bincode::Options;
+use bytes::{Buf, BufMut, BytesMut};
 use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 use tokio::{
-    io::AsyncReadExt,
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     time::{Duration, timeout},
 };
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Client-chosen identifier of a task, echoed back in every [`TaskResponse`].
+///
+/// Identifiers only need to be unique among the requests a client has in
+/// flight on a single connection; the server never interprets them.
+pub type RequestId = u64;
 
 /// Represents failures encountered when reading or writing packets.
 /// 
@@ -35,7 +43,9 @@ pub enum ProtocolError {
     #[error("Network I/O error: {0}")]
     Io(#[from] std::io::Error),
 
-    /// Triggered when a network operation exceeds the 5-second deadline.
+    /// A frame, once started, was not completed within
+    /// [`FrameConfig::read_timeout`], or another network operation (e.g. a
+    /// TLS handshake) outlived its deadline.
     #[error("Request has timed out: {0}")]
     TimeOutError(#[from] tokio::time::error::Elapsed),
 
@@ -43,6 +53,64 @@ pub enum ProtocolError {
     /// to be represented by the protocol.
     #[error("Structure was too big to send")]
     InternalLimitExceeded,
+
+    /// The remote peer refused the session with a typed [`ServerError`] frame.
+    #[error("Peer refused the session: {0}")]
+    Refused(#[from] ServerError),
+
+    /// The peer sent a valid frame that is not allowed at this point of the
+    /// conversation (e.g. a `TaskResponse` in place of a `Welcome`).
+    #[error("Unexpected message during handshake")]
+    UnexpectedMessage,
+
+    /// The server sent a [`Challenge`] but no [`crate::auth::Credentials`]
+    /// were given to answer it.
+    #[error("The server requires authentication but no credentials were provided")]
+    MissingCredentials,
+
+    /// No frame started within [`FrameConfig::idle_timeout`].
+    ///
+    /// Unlike [`ProtocolError::TimeOutError`], no byte of the next frame was
+    /// consumed, so the stream may still be read from.
+    #[error("No frame received within the idle timeout")]
+    Idle,
+}
+
+impl ProtocolError {
+    /// Every value [`ProtocolError::kind`] may return.
+    pub const KINDS: &'static [&'static str] = &[
+        "packet_too_short",
+        "packet_too_large",
+        "malformed",
+        "io",
+        "timeout",
+        "internal_limit",
+        "refused",
+        "unexpected_message",
+        "missing_credentials",
+        "idle",
+    ];
+
+    /// Returns `true` if the peer simply closed the connection between frames.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, ProtocolError::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+    }
+
+    /// Returns a short, stable label for the error, suitable for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProtocolError::PacketTooShort => "packet_too_short",
+            ProtocolError::PacketTooLarge(_) => "packet_too_large",
+            ProtocolError::Bincode(_) => "malformed",
+            ProtocolError::Io(_) => "io",
+            ProtocolError::TimeOutError(_) => "timeout",
+            ProtocolError::InternalLimitExceeded => "internal_limit",
+            ProtocolError::Refused(_) => "refused",
+            ProtocolError::UnexpectedMessage => "unexpected_message",
+            ProtocolError::MissingCredentials => "missing_credentials",
+            ProtocolError::Idle => "idle",
+        }
+    }
 }
 
 /// Top-level container for all network communication.
@@ -55,6 +123,32 @@ pub enum ProtocolMessage {
     TaskRequest(TaskRequest),
     /// A response sent back from a worker containing results or failure status.
     TaskResponse(TaskResponse),
+    /// The first frame a client must send, announcing its protocol version.
+    Hello(Hello),
+    /// The server's answer to a compatible [`Hello`], describing its capabilities.
+    Welcome(Welcome),
+    /// A terminal error sent by the server right before it closes the connection.
+    Error(ServerError),
+    /// Sent instead of a [`Welcome`] by a server requiring authentication.
+    Challenge(Challenge),
+    /// The client's answer to a [`Challenge`].
+    Authenticate(Authenticate),
+    /// Asks the server to abandon the pending request with this id.
+    ///
+    /// A request that has not completed yet is answered with
+    /// [`FailureReason::Cancelled`]; unknown or completed ids are ignored.
+    CancelTask(RequestId),
+    /// Periodic report on a running task that asked for it.
+    Progress(Progress),
+    /// Checks that the peer is alive; it must answer with a [`ProtocolMessage::Pong`]
+    /// echoing the same value.
+    ///
+    /// The server pings connections that stayed silent for
+    /// [`crate::config::ServerConfig::heartbeat_interval`], and clients may
+    /// ping the server at any time after the handshake.
+    Ping(u64),
+    /// The answer to a [`ProtocolMessage::Ping`].
+    Pong(u64),
 }
 
 impl ProtocolMessage {
@@ -67,44 +161,345 @@ impl ProtocolMessage {
     /// Returns [`ProtocolError::PacketTooLarge`] if the serialized size 
     /// exceeds [`MAX_PACKET_SIZE`].
     pub fn into_packet(&self) -> Result<Vec<u8>, ProtocolError> {
-        let payload_size = bincode_config()
-            .serialized_size(self)
-            .map_err(|e| ProtocolError::Bincode(e))? as usize;
-
-        if payload_size > MAX_PACKET_SIZE {
-            return Err(ProtocolError::PacketTooLarge(payload_size));
-        }
-
+        let payload_size = self.payload_size(MAX_PACKET_SIZE)?;
         let mut buffer = Vec::with_capacity(4 + payload_size);
 
         buffer.extend_from_slice(&(payload_size as u32).to_be_bytes());
 
-        bincode_config()
+        bincode_config(MAX_PACKET_SIZE)
             .serialize_into(&mut buffer, self)
-            .map_err(|e| ProtocolError::Bincode(e))?;
+            .map_err(ProtocolError::Bincode)?;
 
         Ok(buffer)
     }
+
+    /// Returns the size of the serialized message, refusing anything larger
+    /// than `limit`.
+    fn payload_size(&self, limit: usize) -> Result<usize, ProtocolError> {
+        let payload_size = bincode_config(limit)
+            .serialized_size(self)
+            .map_err(ProtocolError::Bincode)? as usize;
+
+        if payload_size > limit {
+            return Err(ProtocolError::PacketTooLarge(payload_size));
+        }
+        Ok(payload_size)
+    }
+}
+
+/// Opening frame of every connection.
+///
+/// The server refuses any other message until a `Hello` carrying a matching
+/// [`PROTOCOL_VERSION`] has been received.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    /// The protocol version the client was built against.
+    pub version: u16,
+}
+
+impl Hello {
+    /// Creates a `Hello` announcing the [`PROTOCOL_VERSION`] of this build.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self { version: PROTOCOL_VERSION }
+    }
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Random nonce a client must sign to prove its identity.
+///
+/// See [`crate::auth::AuthConfig`] for how the answer is computed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Random bytes, unique to this connection.
+    pub nonce: Vec<u8>,
+}
+
+/// How far a running task got, sent every
+/// [`crate::config::ServerConfig::progress_interval`] to clients that set
+/// [`HashingPacket::progress`].
+///
+/// Progress frames are best-effort: they are skipped rather than delayed
+/// when the connection is busy. Those of a completed task precede its
+/// [`TaskResponse`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    /// The identifier of the running request.
+    pub id: RequestId,
+    /// Input bytes hashed so far.
+    pub bytes_processed: u64,
+    /// Size of the whole input, when known up front.
+    pub total_bytes: Option<u64>,
+    /// Average hashing speed since the task started, in bytes per second.
+    pub bytes_per_second: u64,
+}
+
+/// A client's proof of identity, answering a [`Challenge`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Authenticate {
+    /// The identity the client claims.
+    pub identity: String,
+    /// HMAC-SHA256 of the nonce followed by the identity, keyed with the
+    /// identity's shared key.
+    pub mac: Vec<u8>,
+}
+
+/// Capabilities advertised by the server once the handshake succeeds.
+///
+/// Clients should use this to avoid sending requests the server is known
+/// to reject (unimplemented algorithms or oversized frames).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Welcome {
+    /// The protocol version spoken by the server.
+    pub version: u16,
+    /// Every [`HashAlgorithms`] the server's workers can actually compute.
+    pub algorithms: Vec<HashAlgorithms>,
+    /// The largest frame payload, in bytes, the server accepts.
+    pub max_packet_size: u32,
+}
+
+/// Typed reasons for which the server terminates a connection.
+///
+/// These are sent inside [`ProtocolMessage::Error`] so that clients can
+/// distinguish a refusal from a plain network failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+pub enum ServerError {
+    /// The client's [`Hello`] announced a version this server does not speak.
+    #[error("Protocol version mismatch: server speaks {server}, client sent {client}")]
+    VersionMismatch {
+        /// The version implemented by the server.
+        server: u16,
+        /// The version announced by the client.
+        client: u16,
+    },
+
+    /// The client sent another message before completing the handshake.
+    #[error("A Hello frame is required before any other message")]
+    HandshakeRequired,
+
+    /// The server is shutting down. Responses to requests it accepted have
+    /// been sent, unless its drain deadline expired first.
+    #[error("The server is shutting down")]
+    ShuttingDown,
+
+    /// The peer is not allowed to use this server, e.g. its Unix credentials
+    /// are not listed in [`crate::unix::UnixConfig`].
+    #[error("The peer is not allowed to connect")]
+    Forbidden,
+
+    /// The client's answer to a [`Challenge`] did not match a known identity
+    /// and key.
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+
+    /// The server already serves as many connections as
+    /// [`crate::config::ServerConfig::max_connections`] allows.
+    #[error("The server has reached its connection limit")]
+    TooManyConnections,
+
+    /// The client's address already has as many connections open as
+    /// [`crate::config::ServerConfig::max_connections_per_ip`] allows.
+    #[error("Too many connections from this address")]
+    TooManyPeerConnections,
 }
 
 /// Represents the final outcome of a worker's hashing operation.
 ///
 /// This response is sent back to the orchestrator once the task execution 
 /// is complete. It distinguishes between a successfully computed hash 
-/// and a terminal failure.
+/// and a terminal failure. Responses to pipelined requests may arrive in
+/// any order; the echoed [`RequestId`] ties each one to its request.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum TaskResponse {
     /// Indicates the task completed successfully.
-    /// 
-    /// The contained [`String`] is the hex-encoded result of the hashing 
-    /// algorithm applied to the target file.
-    Success(String),
+    Success {
+        /// The identifier of the request this response answers.
+        id: RequestId,
+        /// The hex-encoded result of the hashing algorithm applied to the
+        /// target file.
+        digest: String,
+    },
 
     /// Indicates the task could not be completed.
     /// 
     /// This may occur due to missing files, insufficient permissions, 
     /// or unsupported hashing algorithms on the worker side.
-    Failed,
+    Failed {
+        /// The identifier of the request this response answers.
+        id: RequestId,
+        /// Machine-readable category of the failure.
+        reason: FailureReason,
+        /// Optional human-readable details, such as the OS error message.
+        message: Option<String>,
+    },
+
+    /// Indicates the server's queue was full and the task was not run.
+    ///
+    /// Sent in place of a result when the server's
+    /// [`crate::queue::AdmissionPolicy`] turns tasks away instead of making
+    /// the connection wait. The same request may be sent again later.
+    Busy {
+        /// The identifier of the request this response answers.
+        id: RequestId,
+        /// How long the client should wait before sending the request again.
+        retry_after: Duration,
+        /// Number of tasks waiting for a worker when this one was turned away.
+        queue_depth: u32,
+    },
+
+    /// Indicates the client exceeded one of its quotas and the task was not run.
+    ///
+    /// Quotas are set per client by the server's
+    /// [`crate::quota::QuotaConfig`] and shared by all the connections of a
+    /// client.
+    QuotaExceeded {
+        /// The identifier of the request this response answers.
+        id: RequestId,
+        /// The quota the request was refused for.
+        error: QuotaError,
+    },
+}
+
+/// A client quota a request was refused for, sent in
+/// [`TaskResponse::QuotaExceeded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+pub enum QuotaError {
+    /// The client sent requests faster than its rate limit allows.
+    #[error("Rate limit exceeded, retry after {retry_after:?}")]
+    RateLimited {
+        /// How long until the client may send another request.
+        retry_after: Duration,
+    },
+
+    /// The client already has as many requests queued or running as it may.
+    #[error("Too many requests in flight, at most {limit} allowed")]
+    TooManyInFlight {
+        /// The number of requests the client may have in flight.
+        limit: u32,
+    },
+}
+
+impl QuotaError {
+    /// Every value [`QuotaError::kind`] may return.
+    pub const KINDS: &'static [&'static str] = &["rate_limited", "too_many_in_flight"];
+
+    /// Returns a short, stable label for the error, suitable for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QuotaError::RateLimited { .. } => "rate_limited",
+            QuotaError::TooManyInFlight { .. } => "too_many_in_flight",
+        }
+    }
+}
+
+/// Categorizes why a task ended in [`TaskResponse::Failed`].
+///
+/// Callers should branch on this value rather than on the free-form message
+/// to decide whether a request is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureReason {
+    /// The target file does not exist.
+    NotFound,
+    /// The server process is not allowed to read the target file.
+    PermissionDenied,
+    /// The requested algorithm is not implemented by the server.
+    UnsupportedAlgorithm,
+    /// The target is a [`FilePath::Remote`], but remote hashing is disabled
+    /// on the server.
+    RemoteDisabled,
+    /// The task did not complete before its deadline.
+    Timeout,
+    /// The worker executing the task panicked.
+    WorkerPanic,
+    /// The input exceeds the maximum size the server accepts.
+    TooLarge,
+    /// Any other I/O failure while reading the input.
+    Io,
+    /// The request parameters are inconsistent (e.g. an output length given
+    /// for a fixed-size algorithm).
+    InvalidRequest,
+    /// The remote URL, or one of its redirects, targets a host outside the
+    /// server's allowlist.
+    RemoteNotAllowed,
+    /// The remote server could not be reached or answered with an error.
+    RemoteUnavailable,
+    /// The local path resolves outside the server's allowed roots, or is not
+    /// a regular file.
+    PathNotAllowed,
+    /// The client cancelled the request, or disconnected before it completed.
+    Cancelled,
+}
+
+impl FailureReason {
+    /// Every failure reason, in declaration order.
+    pub const ALL: &'static [FailureReason] = &[
+        FailureReason::NotFound,
+        FailureReason::PermissionDenied,
+        FailureReason::UnsupportedAlgorithm,
+        FailureReason::RemoteDisabled,
+        FailureReason::Timeout,
+        FailureReason::WorkerPanic,
+        FailureReason::TooLarge,
+        FailureReason::Io,
+        FailureReason::InvalidRequest,
+        FailureReason::RemoteNotAllowed,
+        FailureReason::RemoteUnavailable,
+        FailureReason::PathNotAllowed,
+        FailureReason::Cancelled,
+    ];
+
+    /// Returns the snake_case name of the reason (e.g. `not_found`).
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureReason::NotFound => "not_found",
+            FailureReason::PermissionDenied => "permission_denied",
+            FailureReason::UnsupportedAlgorithm => "unsupported_algorithm",
+            FailureReason::RemoteDisabled => "remote_disabled",
+            FailureReason::Timeout => "timeout",
+            FailureReason::WorkerPanic => "worker_panic",
+            FailureReason::TooLarge => "too_large",
+            FailureReason::Io => "io",
+            FailureReason::InvalidRequest => "invalid_request",
+            FailureReason::RemoteNotAllowed => "remote_not_allowed",
+            FailureReason::RemoteUnavailable => "remote_unavailable",
+            FailureReason::PathNotAllowed => "path_not_allowed",
+            FailureReason::Cancelled => "cancelled",
+        }
+    }
+
+    /// Returns `true` if the same request may succeed when sent again later.
+    ///
+    /// Failures tied to the request itself (missing file, unsupported
+    /// algorithm...) are permanent, while transient conditions are not.
+    #[inline]
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            FailureReason::Timeout
+                | FailureReason::WorkerPanic
+                | FailureReason::Io
+                | FailureReason::RemoteUnavailable
+        )
+    }
+}
+
+impl TaskResponse {
+    /// Returns the [`RequestId`] of the request this response answers.
+    #[inline]
+    pub fn id(&self) -> RequestId {
+        match self {
+            TaskResponse::Success { id, .. }
+            | TaskResponse::Failed { id, .. }
+            | TaskResponse::Busy { id, .. }
+            | TaskResponse::QuotaExceeded { id, .. } => *id,
+        }
+    }
 }
 
 /// The primary dispatch mechanism for worker assignments.
@@ -118,7 +513,80 @@ pub enum TaskRequest {
     /// 
     /// The wrapped [`HashingPacket`] defines the target algorithm and 
     /// the file location (local or remote) required for execution.
-    HashPacket(HashingPacket),
+    HashPacket {
+        /// Identifier echoed back in the matching [`TaskResponse`].
+        id: RequestId,
+        /// The parameters of the hashing operation.
+        packet: HashingPacket,
+        /// How urgently the task should run.
+        priority: Priority,
+    },
+}
+
+/// How urgently a task should run, relative to the other queued tasks.
+///
+/// Workers take interactive tasks first, then normal, then bulk ones. So
+/// that bulk work still makes progress under a steady stream of urgent
+/// tasks, a priority passed over too many times in a row goes next, see
+/// [`crate::queue::SchedulingConfig::starvation_limit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Priority {
+    /// A task someone is waiting on, e.g. a verification before a download.
+    Interactive,
+    /// The default priority.
+    #[default]
+    Normal,
+    /// Background work, e.g. re-hashing a whole archive.
+    Bulk,
+}
+
+impl Priority {
+    /// Every priority, the most urgent first.
+    pub const ALL: &'static [Priority] = &[Priority::Interactive, Priority::Normal, Priority::Bulk];
+
+    /// Returns the lowercase name of the priority (e.g. `bulk`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Normal => "normal",
+            Priority::Bulk => "bulk",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned when parsing an unknown priority name.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown priority: {0}")]
+pub struct ParsePriorityError(String);
+
+impl FromStr for Priority {
+    type Err = ParsePriorityError;
+
+    /// Parses a name as returned by [`Priority::name`], ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|priority| priority.name() == wanted)
+            .ok_or_else(|| ParsePriorityError(s.to_owned()))
+    }
+}
+
+impl TaskRequest {
+    /// Returns the client-chosen [`RequestId`] of this request.
+    #[inline]
+    pub fn id(&self) -> RequestId {
+        match self {
+            TaskRequest::HashPacket { id, .. } => *id,
+        }
+    }
 }
 
 /// Data payload containing the parameters for a hashing operation.
@@ -126,12 +594,22 @@ pub enum TaskRequest {
 /// This structure encapsulates everything a worker needs to execute a task:
 /// the specific cryptographic algorithm to use and the location of the target file.
 /// It is designed to be serialized as part of a [`TaskRequest`].
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HashingPacket {
     /// The cryptographic hash function to be applied (e.g., SHA-256, BLAKE3).
     pub algorithm: HashAlgorithms,
     /// The location of the file to be processed.
     pub path: FilePath,
+    /// Number of output bytes requested from an extendable-output function.
+    ///
+    /// Only meaningful for XOFs such as [`HashAlgorithms::SHAKE128`]; `None`
+    /// selects [`HashAlgorithms::default_output_len`]. It must be `None` for
+    /// fixed-size algorithms.
+    pub output_len: Option<u32>,
+    /// Asks the server to send [`Progress`] frames while the task runs.
+    ///
+    /// Off by default, so that small tasks do not pay for the reports.
+    pub progress: bool,
 }
 
 impl HashingPacket {
@@ -146,16 +624,53 @@ impl HashingPacket {
     pub fn path(&self) -> &FilePath {
         &self.path
     }
+
+    /// Returns the requested XOF output length, if any.
+    #[inline]
+    pub fn output_len(&self) -> Option<u32> {
+        self.output_len
+    }
+
+    /// Returns `true` if the client asked for [`Progress`] frames.
+    #[inline]
+    pub fn progress(&self) -> bool {
+        self.progress
+    }
 }
 
-fn bincode_config() -> impl bincode::Options {
+fn bincode_config(limit: usize) -> impl bincode::Options {
     bincode::DefaultOptions::new()
-        .with_limit(MAX_PACKET_SIZE as u64)
+        .with_limit(limit as u64)
         .with_big_endian()
         .with_fixint_encoding()
 }
 
-/// Reads a [`ProtocolMessage`] from a TCP stream with a 5-second timeout.
+/// Limits applied while reading frames from a peer.
+///
+/// The defaults match [`MAX_PACKET_SIZE`], a 5-second wait for the next frame
+/// and a 5-second deadline to complete it; a server may tune them through its
+/// [`crate::config::ServerConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameConfig {
+    /// Largest payload, in bytes, accepted in a single frame.
+    pub max_packet_size: usize,
+    /// Deadline for receiving the rest of a frame once its first byte arrived.
+    pub read_timeout: Duration,
+    /// How long to wait for the first byte of a frame; `None` waits forever.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        Self {
+            max_packet_size: MAX_PACKET_SIZE,
+            read_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+/// Reads a [`ProtocolMessage`] from any [`AsyncRead`] stream with 5-second timeouts.
 /// 
 /// This function performs two reads:
 /// 1. Reads 4 bytes to determine the payload length.
@@ -166,27 +681,249 @@ fn bincode_config() -> impl bincode::Options {
 /// and drops connections that do not complete the transfer within the timeout.
 ///
 /// # Errors
-/// Returns [`ProtocolError::TimeOutError`] if the client is too slow.
-pub async fn read_protocol(stream: &mut TcpStream) -> Result<ProtocolMessage, ProtocolError> {
-    let read_timeout = Duration::from_secs(5);
+/// Returns [`ProtocolError::Idle`] if no frame starts in time, and
+/// [`ProtocolError::TimeOutError`] if the client is too slow to complete it.
+pub async fn read_protocol<R>(stream: &mut R) -> Result<ProtocolMessage, ProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    read_protocol_with(stream, &FrameConfig::default()).await
+}
+
+/// Reads a [`ProtocolMessage`] from a stream under custom [`FrameConfig`] limits.
+///
+/// This behaves like [`read_protocol`] but enforces the given limits instead
+/// of the protocol defaults. Waiting for a frame to start and receiving it
+/// are timed separately, so that a long-lived connection may stay quiet for
+/// `limits.idle_timeout` while a peer trickling bytes is still cut off after
+/// `limits.read_timeout`.
+///
+/// # Errors
+/// Returns [`ProtocolError::PacketTooLarge`] if the header announces more
+/// than `limits.max_packet_size` bytes, [`ProtocolError::Idle`] if no frame
+/// starts in time, or [`ProtocolError::TimeOutError`] if the client is too
+/// slow to complete it.
+pub async fn read_protocol_with<R>(
+    stream: &mut R,
+    limits: &FrameConfig,
+) -> Result<ProtocolMessage, ProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    let max_packet_size = limits.max_packet_size;
+    let mut len_buf = [0u8; 4];
+    // A single-byte read either completes or consumes nothing, so giving up
+    // on it leaves the stream usable.
+    let first_byte = stream.read_exact(&mut len_buf[..1]);
+    match limits.idle_timeout {
+        Some(idle) => timeout(idle, first_byte).await.map_err(|_| ProtocolError::Idle)??,
+        None => first_byte.await?,
+    };
+
     let read_future = async {
-        let mut len_buf = [0u8; 4];
-        stream.read_exact(&mut len_buf).await?;
+        stream.read_exact(&mut len_buf[1..]).await?;
         let packet_size = PacketSize::from_slice(&len_buf)?;
 
         let len = packet_size.into();
 
-        if len > MAX_PACKET_SIZE {
+        if len > max_packet_size {
             return Err(ProtocolError::PacketTooLarge(len));
         }
         let mut payload = vec![0u8; len];
         stream.read_exact(&mut payload).await?;
-        let task: ProtocolMessage = bincode_config().deserialize(&payload)?;
+        let task: ProtocolMessage = bincode_config(max_packet_size).deserialize(&payload)?;
 
         Ok(task)
     };
 
-    timeout(read_timeout, read_future).await?
+    timeout(limits.read_timeout, read_future).await?
+}
+
+/// Writes a [`ProtocolMessage`] as a single frame and flushes the stream.
+///
+/// This is the counterpart of [`read_protocol`] and works over any transport:
+/// TCP or Unix sockets, TLS streams, in-memory pipes, or stdin/stdout joined
+/// with [`tokio::io::join`].
+///
+/// # Errors
+/// Returns [`ProtocolError::PacketTooLarge`] if the serialized message exceeds
+/// [`MAX_PACKET_SIZE`], or [`ProtocolError::Io`] if the stream fails.
+///
+/// # Examples
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), task_scheduler::protocol::ProtocolError> {
+/// use task_scheduler::protocol::{Hello, ProtocolMessage, read_protocol, write_protocol};
+///
+/// let (mut client, mut server) = tokio::io::duplex(1024);
+/// write_protocol(&mut client, &ProtocolMessage::Hello(Hello::new())).await?;
+/// assert!(matches!(read_protocol(&mut server).await?, ProtocolMessage::Hello(_)));
+/// # Ok(())
+/// # }
+/// ```
+pub async fn write_protocol<W>(stream: &mut W, message: &ProtocolMessage) -> Result<(), ProtocolError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let packet = message.into_packet()?;
+    stream.write_all(&packet).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// A [`tokio_util::codec`] implementation of the framing, for use with
+/// [`tokio_util::codec::Framed`], `FramedRead` or `FramedWrite`.
+///
+/// The codec enforces a maximum payload size on both directions but, unlike
+/// [`read_protocol_with`], applies no read deadline: wrap the stream calls in
+/// [`tokio::time::timeout`] where one is needed.
+///
+/// # Examples
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), task_scheduler::protocol::ProtocolError> {
+/// use futures_util::{SinkExt, StreamExt};
+/// use task_scheduler::protocol::{Hello, ProtocolCodec, ProtocolMessage};
+/// use tokio_util::codec::Framed;
+///
+/// let (client, server) = tokio::io::duplex(1024);
+/// let mut client = Framed::new(client, ProtocolCodec::default());
+/// let mut server = Framed::new(server, ProtocolCodec::default());
+///
+/// client.send(ProtocolMessage::Hello(Hello::new())).await?;
+/// assert!(matches!(server.next().await, Some(Ok(ProtocolMessage::Hello(_)))));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolCodec {
+    max_packet_size: usize,
+}
+
+impl ProtocolCodec {
+    /// Creates a codec refusing payloads larger than `max_packet_size` bytes.
+    #[inline]
+    #[must_use]
+    pub fn new(max_packet_size: usize) -> Self {
+        Self { max_packet_size }
+    }
+
+    /// Returns the largest payload, in bytes, this codec accepts.
+    #[inline]
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+}
+
+impl Default for ProtocolCodec {
+    /// Creates a codec enforcing [`MAX_PACKET_SIZE`].
+    fn default() -> Self {
+        Self::new(MAX_PACKET_SIZE)
+    }
+}
+
+impl From<&FrameConfig> for ProtocolCodec {
+    fn from(limits: &FrameConfig) -> Self {
+        Self::new(limits.max_packet_size)
+    }
+}
+
+impl Decoder for ProtocolCodec {
+    type Item = ProtocolMessage;
+    type Error = ProtocolError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<ProtocolMessage>, ProtocolError> {
+        let Ok(packet_size) = PacketSize::from_slice(src) else {
+            return Ok(None);
+        };
+        let len = usize::from(packet_size);
+
+        if len > self.max_packet_size {
+            return Err(ProtocolError::PacketTooLarge(len));
+        }
+        if src.len() < 4 + len {
+            src.reserve(4 + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(4);
+        let payload = src.split_to(len);
+        let message = bincode_config(self.max_packet_size).deserialize(&payload)?;
+        Ok(Some(message))
+    }
+}
+
+impl Encoder<ProtocolMessage> for ProtocolCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, message: ProtocolMessage, dst: &mut BytesMut) -> Result<(), ProtocolError> {
+        self.encode(&message, dst)
+    }
+}
+
+impl Encoder<&ProtocolMessage> for ProtocolCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, message: &ProtocolMessage, dst: &mut BytesMut) -> Result<(), ProtocolError> {
+        let payload_size = message.payload_size(self.max_packet_size)?;
+        dst.reserve(4 + payload_size);
+        dst.put_slice(&PacketSize::from(payload_size as u32).to_bytes());
+
+        bincode_config(self.max_packet_size)
+            .serialize_into(dst.writer(), message)
+            .map_err(ProtocolError::Bincode)
+    }
+}
+
+/// Performs the client side of the `Hello`/`Welcome` handshake.
+///
+/// Sends a [`Hello`] for the current [`PROTOCOL_VERSION`] and waits for the
+/// server's answer. This must be called once, before any [`TaskRequest`].
+///
+/// # Errors
+/// Returns [`ProtocolError::Refused`] if the server answered with a
+/// [`ServerError`] frame, [`ProtocolError::MissingCredentials`] if it requires
+/// authentication, or [`ProtocolError::UnexpectedMessage`] if it answered with
+/// anything other than a [`Welcome`].
+pub async fn client_handshake<S>(stream: &mut S) -> Result<Welcome, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    client_handshake_with(stream, None).await
+}
+
+/// Performs the client side of the handshake, authenticating with
+/// `credentials` if the server sends a [`Challenge`].
+///
+/// # Errors
+/// Returns [`ProtocolError::Refused`] with [`ServerError::AuthenticationFailed`]
+/// if the server rejected the credentials, and otherwise the same errors as
+/// [`client_handshake`].
+pub async fn client_handshake_with<S>(
+    stream: &mut S,
+    credentials: Option<&Credentials>,
+) -> Result<Welcome, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    write_protocol(stream, &ProtocolMessage::Hello(Hello::new())).await?;
+
+    let challenge = match read_protocol(stream).await? {
+        ProtocolMessage::Challenge(challenge) => challenge,
+        answer => return expect_welcome(answer),
+    };
+    let credentials = credentials.ok_or(ProtocolError::MissingCredentials)?;
+    write_protocol(stream, &ProtocolMessage::Authenticate(credentials.respond(&challenge))).await?;
+
+    expect_welcome(read_protocol(stream).await?)
+}
+
+fn expect_welcome(answer: ProtocolMessage) -> Result<Welcome, ProtocolError> {
+    match answer {
+        ProtocolMessage::Welcome(welcome) => Ok(welcome),
+        ProtocolMessage::Error(e) => Err(ProtocolError::Refused(e)),
+        _ => Err(ProtocolError::UnexpectedMessage),
+    }
 }
 
 /// A type-safe wrapper representing the size of a protocol packet.
@@ -208,7 +945,7 @@ impl PacketSize {
     ///
     /// # Examples
     /// ```
-    /// # use task_scheduler::network::PacketSize;
+    /// # use task_scheduler::protocol::PacketSize;
     /// let raw_header = [0, 0, 0, 100]; // 100 bytes in Big-Endian
     /// let size = PacketSize::from_slice(&raw_header).unwrap();
     /// assert_eq!(usize::from(size), 100);
@@ -226,7 +963,8 @@ impl PacketSize {
 
     /// Converts the packet size into its 4-byte Big-Endian network representation.
     ///
-    /// This is used when prefixing a payload before sending it over a [`TcpStream`].
+    /// This is used when prefixing a payload before sending it over the
+    /// transport.
     #[inline]
     #[must_use]
     pub fn to_bytes(self) -> [u8; 4] {
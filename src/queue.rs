@@ -0,0 +1,442 @@
+use crate::{
+    protocol::Priority,
+    quota::{ClientKey, QuotaConfig},
+    workers::WorkItem,
+};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::pin,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+/// What happens to a task submitted while the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdmissionPolicy {
+    /// The connection stops reading requests until a worker frees a slot.
+    #[default]
+    Block,
+    /// The new task is answered right away with
+    /// [`crate::protocol::TaskResponse::Busy`].
+    Reject,
+    /// The oldest queued task of the lowest [`Priority`] is answered with
+    /// [`crate::protocol::TaskResponse::Busy`] to make room for the new one.
+    /// Unless the queue is [`SchedulingPolicy::Fifo`], that is the oldest task
+    /// of the client with the most queued tasks of that priority. Queued
+    /// tasks more urgent than the new one are never shed: if only those are
+    /// left, the new task is rejected as under [`AdmissionPolicy::Reject`].
+    ShedOldest,
+}
+
+/// Admission control settings, the `[admission]` section of a
+/// [`crate::config::ServerConfig`].
+///
+/// ```toml
+/// [admission]
+/// policy = "reject"
+/// retry_after = "500ms"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdmissionConfig {
+    /// How tasks submitted to a full queue are handled.
+    pub policy: AdmissionPolicy,
+    /// Delay suggested to clients turned away by a full queue.
+    #[serde(with = "humantime_serde")]
+    pub retry_after: Duration,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            policy: AdmissionPolicy::Block,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+}
+
+/// The order in which workers take the tasks of different clients.
+///
+/// Clients are told apart as for [`crate::quota::QuotaConfig`]: by identity
+/// when authenticated, by address otherwise. The tasks of a single client
+/// always run in the order they were submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulingPolicy {
+    /// Tasks run in the order they were submitted, whoever sent them.
+    Fifo,
+    /// Clients with queued tasks take turns, running one task each.
+    #[default]
+    RoundRobin,
+    /// Clients with queued tasks take turns, running as many tasks per turn
+    /// as the weight of their tier.
+    Weighted,
+}
+
+/// Scheduling settings, the `[scheduling]` section of a
+/// [`crate::config::ServerConfig`].
+///
+/// ```toml
+/// [scheduling]
+/// policy = "weighted"
+/// default_weight = 1
+/// starvation_limit = 8
+///
+/// [scheduling.weights]
+/// interactive = 4
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulingConfig {
+    /// How the workers share out between clients.
+    pub policy: SchedulingPolicy,
+    /// Tasks per turn of the clients of each [`crate::quota::QuotaConfig`]
+    /// tier, under [`SchedulingPolicy::Weighted`].
+    pub weights: HashMap<String, u32>,
+    /// Tasks per turn of the clients whose tier has no weight.
+    pub default_weight: u32,
+    /// Tasks of higher priorities that may run in a row while tasks of a
+    /// lower [`Priority`] wait; the next worker then takes a lower one.
+    pub starvation_limit: u32,
+}
+
+impl Default for SchedulingConfig {
+    fn default() -> Self {
+        Self {
+            policy: SchedulingPolicy::RoundRobin,
+            weights: HashMap::new(),
+            default_weight: 1,
+            starvation_limit: 8,
+        }
+    }
+}
+
+impl SchedulingConfig {
+    /// Describes the first unusable entry, if any.
+    pub(crate) fn validate(&self, quotas: &QuotaConfig) -> Result<(), String> {
+        if self.starvation_limit == 0 {
+            return Err(String::from("scheduling.starvation_limit must be at least 1"));
+        }
+        if self.default_weight == 0 {
+            return Err(String::from("scheduling.default_weight must be at least 1"));
+        }
+        for (tier, weight) in &self.weights {
+            if !quotas.tiers.contains_key(tier) {
+                return Err(format!("scheduling weight of {} names no quotas.tiers entry", tier));
+            }
+            if *weight == 0 {
+                return Err(format!("scheduling weight of {} must be at least 1", tier));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the tasks per turn of `client`.
+    pub(crate) fn weight_of(&self, client: &ClientKey, quotas: &QuotaConfig) -> u32 {
+        match self.policy {
+            SchedulingPolicy::Weighted => quotas
+                .tier_of(client)
+                .and_then(|tier| self.weights.get(tier))
+                .copied()
+                .unwrap_or(self.default_weight),
+            SchedulingPolicy::Fifo | SchedulingPolicy::RoundRobin => 1,
+        }
+    }
+}
+
+/// The client a [`WorkItem`] is scheduled for, shared by all its items.
+#[derive(Debug)]
+pub(crate) struct Flow {
+    client: ClientKey,
+    weight: u32,
+}
+
+impl Flow {
+    pub(crate) fn new(client: ClientKey, weight: u32) -> Self {
+        Self { client, weight }
+    }
+}
+
+/// Outcome of [`WorkQueue::submit`].
+#[derive(Debug)]
+pub enum Admission {
+    /// The task was queued.
+    Queued,
+    /// The task was queued in place of this older one, which was removed
+    /// from the queue and must be answered by the caller.
+    Shed(WorkItem),
+    /// The queue is full and its policy rejects new tasks, or every queued
+    /// task is more urgent than this one.
+    Full(WorkItem),
+    /// The queue was closed: the worker pool is gone.
+    Closed(WorkItem),
+}
+
+/// The bounded queue connections submit their tasks to and workers take
+/// them from, applying an [`AdmissionPolicy`] once it is full.
+///
+/// Tasks are kept in one level per [`Priority`], and within a level in one
+/// queue per client, served according to a [`SchedulingPolicy`]. Items
+/// created without a client share one queue.
+#[derive(Debug)]
+pub struct WorkQueue {
+    state: Mutex<State>,
+    capacity: usize,
+    policy: AdmissionPolicy,
+    scheduling: SchedulingPolicy,
+    starvation_limit: u32,
+    /// Notified when a task is queued or the queue is closed.
+    available: Notify,
+    /// Notified when a task leaves the queue or the queue is closed.
+    space: Notify,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// One level per [`Priority`], the most urgent first.
+    levels: [Level; Priority::ALL.len()],
+    /// Tasks of higher priorities taken while each level had tasks waiting.
+    passed_over: [u32; Priority::ALL.len()],
+    len: usize,
+    /// Submission order of the next task, to find the oldest one.
+    next_seq: u64,
+    closed: bool,
+}
+
+impl State {
+    fn push(&mut self, item: WorkItem) {
+        let level = item.priority() as usize;
+        if self.levels[level].is_empty() {
+            self.passed_over[level] = 0;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.len += 1;
+        self.levels[level].push(seq, item);
+    }
+
+    /// Takes the next task of the most urgent level, unless a less urgent
+    /// one was passed over `starvation_limit` times in a row.
+    fn pop(&mut self, scheduling: SchedulingPolicy, starvation_limit: u32) -> Option<WorkItem> {
+        let waiting = |level: &usize| !self.levels[*level].is_empty();
+        let level = (0..self.levels.len())
+            .filter(waiting)
+            .find(|level| self.passed_over[*level] >= starvation_limit)
+            .or_else(|| (0..self.levels.len()).find(waiting))?;
+        let item = self.levels[level].pop(scheduling)?;
+        self.passed_over[level] = 0;
+        for lower in level + 1..self.levels.len() {
+            if !self.levels[lower].is_empty() {
+                self.passed_over[lower] += 1;
+            }
+        }
+        self.len -= 1;
+        Some(item)
+    }
+
+    /// Removes the task to shed from the least urgent level holding one,
+    /// provided it is no more urgent than `priority`.
+    fn shed(&mut self, scheduling: SchedulingPolicy, priority: Priority) -> Option<WorkItem> {
+        let item = self.levels[priority as usize..]
+            .iter_mut()
+            .rev()
+            .find(|level| !level.is_empty())?
+            .shed(scheduling)?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+/// The queued tasks of one priority, by client.
+#[derive(Debug, Default)]
+struct Level {
+    queues: HashMap<Option<ClientKey>, ClientQueue>,
+    /// Clients with queued tasks, the one whose turn it is first.
+    turns: VecDeque<Option<ClientKey>>,
+}
+
+/// The queued tasks of one client.
+#[derive(Debug)]
+struct ClientQueue {
+    items: VecDeque<(u64, WorkItem)>,
+    weight: u32,
+    /// Tasks left to run before the turn passes to the next client.
+    credit: u32,
+}
+
+impl Level {
+    fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    fn push(&mut self, seq: u64, item: WorkItem) {
+        let (key, weight) = match item.flow() {
+            Some(flow) => (Some(flow.client.clone()), flow.weight),
+            None => (None, 1),
+        };
+        if let Some(queue) = self.queues.get_mut(&key) {
+            queue.items.push_back((seq, item));
+            return;
+        }
+        self.turns.push_back(key.clone());
+        let queue = ClientQueue {
+            items: VecDeque::from([(seq, item)]),
+            weight,
+            credit: weight,
+        };
+        self.queues.insert(key, queue);
+    }
+
+    /// Takes the next task of the client whose turn it is.
+    fn pop(&mut self, scheduling: SchedulingPolicy) -> Option<WorkItem> {
+        let key = match scheduling {
+            SchedulingPolicy::Fifo => self.oldest()?,
+            SchedulingPolicy::RoundRobin | SchedulingPolicy::Weighted => self.turns.front()?.clone(),
+        };
+        let queue = self.queues.get_mut(&key).expect("clients with turns have a queue");
+        let (_, item) = queue.items.pop_front().expect("client queues are never empty");
+        queue.credit -= 1;
+        if queue.items.is_empty() {
+            self.remove(&key);
+        } else if queue.credit == 0 {
+            queue.credit = queue.weight;
+            self.turns.rotate_left(1);
+        }
+        Some(item)
+    }
+
+    /// Removes the task to shed: the oldest one, or under fair scheduling the
+    /// oldest one of the client with the most queued tasks.
+    fn shed(&mut self, scheduling: SchedulingPolicy) -> Option<WorkItem> {
+        let key = match scheduling {
+            SchedulingPolicy::Fifo => self.oldest()?,
+            SchedulingPolicy::RoundRobin | SchedulingPolicy::Weighted => self
+                .turns
+                .iter()
+                .max_by_key(|key| self.queues[*key].items.len())?
+                .clone(),
+        };
+        let queue = self.queues.get_mut(&key)?;
+        let (_, item) = queue.items.pop_front()?;
+        if queue.items.is_empty() {
+            self.remove(&key);
+        }
+        Some(item)
+    }
+
+    /// Returns the client whose next task was submitted first.
+    fn oldest(&self) -> Option<Option<ClientKey>> {
+        self.queues
+            .iter()
+            .min_by_key(|(_, queue)| queue.items.front().map(|(seq, _)| *seq))
+            .map(|(key, _)| key.clone())
+    }
+
+    fn remove(&mut self, key: &Option<ClientKey>) {
+        self.queues.remove(key);
+        self.turns.retain(|turn| turn != key);
+    }
+}
+
+impl WorkQueue {
+    /// Creates an empty queue holding at most `capacity` tasks, scheduled
+    /// according to `scheduling`.
+    pub fn new(capacity: usize, policy: AdmissionPolicy, scheduling: &SchedulingConfig) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            capacity,
+            policy,
+            scheduling: scheduling.policy,
+            starvation_limit: scheduling.starvation_limit,
+            available: Notify::new(),
+            space: Notify::new(),
+        }
+    }
+
+    /// Number of tasks waiting for a worker.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().len
+    }
+
+    /// Returns `true` if no task is waiting for a worker.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Largest number of tasks the queue holds.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Queues `item`, applying the admission policy if the queue is full.
+    ///
+    /// Only [`AdmissionPolicy::Block`] ever waits. Dropping the returned
+    /// future before it completes leaves the queue untouched.
+    pub async fn submit(&self, item: WorkItem) -> Admission {
+        loop {
+            let mut space = pin!(self.space.notified());
+            space.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return Admission::Closed(item);
+                }
+                if state.len < self.capacity {
+                    state.push(item);
+                    drop(state);
+                    self.available.notify_one();
+                    return Admission::Queued;
+                }
+                match self.policy {
+                    AdmissionPolicy::Block => {}
+                    AdmissionPolicy::Reject => return Admission::Full(item),
+                    AdmissionPolicy::ShedOldest => match state.shed(self.scheduling, item.priority()) {
+                        Some(oldest) => {
+                            state.push(item);
+                            return Admission::Shed(oldest);
+                        }
+                        None => return Admission::Full(item),
+                    },
+                }
+            }
+            space.await;
+        }
+    }
+
+    /// Takes the next task of the most urgent [`Priority`], according to the
+    /// [`SchedulingPolicy`], waiting for one if the queue is empty.
+    ///
+    /// A priority passed over by [`SchedulingConfig::starvation_limit`]
+    /// tasks in a row while its own tasks waited goes first.
+    ///
+    /// Returns `None` once the queue is closed and empty.
+    pub async fn pop(&self) -> Option<WorkItem> {
+        loop {
+            let mut available = pin!(self.available.notified());
+            available.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(item) = state.pop(self.scheduling, self.starvation_limit) {
+                    drop(state);
+                    self.space.notify_one();
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            available.await;
+        }
+    }
+
+    /// Refuses further tasks and lets the workers exit once the queue is empty.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.available.notify_waiters();
+        self.space.notify_waiters();
+    }
+}
@@ -0,0 +1,261 @@
+use crate::protocol::QuotaError;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Limits applied to a single client, across all of its connections.
+///
+/// Requests beyond a limit are answered with
+/// [`crate::protocol::TaskResponse::QuotaExceeded`] instead of being queued.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    /// Requests per second a client may sustain, refilling a token bucket.
+    /// `None` disables the rate limit.
+    pub rate: Option<f64>,
+    /// Requests a client may send at once after a quiet period, the size of
+    /// its token bucket. Defaults to one second worth of `rate`.
+    pub burst: Option<u32>,
+    /// Requests a client may have queued or running at once. `None` disables
+    /// the limit.
+    pub max_in_flight: Option<u32>,
+}
+
+impl Quota {
+    /// Size of the token bucket, at least one request.
+    fn capacity(&self) -> f64 {
+        match (self.burst, self.rate) {
+            (Some(burst), _) => f64::from(burst),
+            (None, Some(rate)) => rate.ceil().max(1.0),
+            (None, None) => 0.0,
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if self.rate.is_some_and(|rate| !rate.is_finite() || rate <= 0.0) {
+            return Err(format!("{}.rate must be a positive number", name));
+        }
+        if self.burst == Some(0) {
+            return Err(format!("{}.burst must be at least 1", name));
+        }
+        if self.burst.is_some() && self.rate.is_none() {
+            return Err(format!("{}.burst requires a rate", name));
+        }
+        if self.max_in_flight == Some(0) {
+            return Err(format!("{}.max_in_flight must be at least 1", name));
+        }
+        Ok(())
+    }
+}
+
+/// Per-client quotas, the `[quotas]` section of a
+/// [`crate::config::ServerConfig`].
+///
+/// Clients are told apart by their authenticated identity or, when the
+/// server does not require authentication, by their IP address (or their
+/// user id on the Unix socket). Each client gets the quota of its tier, or
+/// the default one if it was not assigned a tier.
+///
+/// ```toml
+/// [quotas.default]
+/// rate = 20.0
+/// burst = 50
+/// max_in_flight = 8
+///
+/// [quotas.tiers.batch]
+/// rate = 200.0
+/// max_in_flight = 64
+///
+/// [quotas.identities]
+/// ci-runner = "batch"
+///
+/// [quotas.addresses]
+/// "10.0.0.12" = "batch"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Quota of the clients not assigned to a tier. Unlimited by default.
+    pub default: Quota,
+    /// Named quotas clients can be assigned to.
+    pub tiers: HashMap<String, Quota>,
+    /// Tier of authenticated clients, by identity.
+    pub identities: HashMap<String, String>,
+    /// Tier of unauthenticated TCP clients, by IP address.
+    pub addresses: HashMap<IpAddr, String>,
+}
+
+impl QuotaConfig {
+    /// Assigns an authenticated identity to a tier.
+    pub fn with_identity(mut self, identity: impl Into<String>, tier: impl Into<String>) -> Self {
+        self.identities.insert(identity.into(), tier.into());
+        self
+    }
+
+    /// Adds a named tier.
+    pub fn with_tier(mut self, name: impl Into<String>, quota: Quota) -> Self {
+        self.tiers.insert(name.into(), quota);
+        self
+    }
+
+    /// Describes the first unusable entry, if any.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        self.default.validate("quotas.default")?;
+        for (name, quota) in &self.tiers {
+            quota.validate(&format!("quotas.tiers.{}", name))?;
+        }
+        let assigned = self.identities.values().chain(self.addresses.values());
+        for tier in assigned {
+            if !self.tiers.contains_key(tier) {
+                return Err(format!("quota tier {} is not defined in quotas.tiers", tier));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the tier `client` is assigned to, if any.
+    pub(crate) fn tier_of(&self, client: &ClientKey) -> Option<&str> {
+        let tier = match client {
+            ClientKey::Identity(identity) => self.identities.get(identity),
+            ClientKey::Address(addr) => self.addresses.get(addr),
+            ClientKey::LocalUser(_) => None,
+        };
+        tier.map(String::as_str)
+    }
+
+    /// Returns the quota applying to `client`.
+    fn quota_for(&self, client: &ClientKey) -> &Quota {
+        self.tier_of(client)
+            .and_then(|tier| self.tiers.get(tier))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// What a client is recognized by when applying its quota and sharing the
+/// workers with other clients.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum ClientKey {
+    /// A client that authenticated with this identity.
+    Identity(String),
+    /// An unauthenticated TCP client.
+    Address(IpAddr),
+    /// An unauthenticated client of the Unix socket, by user id.
+    LocalUser(u32),
+}
+
+/// The quota state of every client of a server.
+#[derive(Debug)]
+pub(crate) struct Quotas {
+    config: QuotaConfig,
+    clients: Mutex<HashMap<ClientKey, Arc<ClientQuota>>>,
+}
+
+impl Quotas {
+    pub(crate) fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            clients: Mutex::default(),
+        }
+    }
+
+    /// Returns the state shared by every connection of `client`.
+    ///
+    /// Clients that have neither a connection nor a task left, and whose
+    /// bucket is full again, are forgotten on the way: a fresh state would be
+    /// identical.
+    pub(crate) fn client(&self, client: ClientKey) -> Arc<ClientQuota> {
+        let mut clients = self.clients.lock().unwrap();
+        let now = Instant::now();
+        clients.retain(|_, state| Arc::strong_count(state) > 1 || !state.is_rested(now));
+
+        let quota = self.config.quota_for(&client);
+        let state = clients
+            .entry(client)
+            .or_insert_with(|| Arc::new(ClientQuota::new(quota.clone())));
+        Arc::clone(state)
+    }
+}
+
+/// The token bucket and in-flight count of one client.
+#[derive(Debug)]
+pub(crate) struct ClientQuota {
+    quota: Quota,
+    bucket: Mutex<Bucket>,
+    in_flight: AtomicU32,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl ClientQuota {
+    fn new(quota: Quota) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: quota.capacity(),
+                refilled: Instant::now(),
+            }),
+            quota,
+            in_flight: AtomicU32::new(0),
+        }
+    }
+
+    /// Admits one more request of the client, which counts as in flight
+    /// until the returned lease is dropped.
+    ///
+    /// # Errors
+    /// Returns the [`QuotaError`] the request violates. A refused request
+    /// consumes no part of the quota.
+    pub(crate) fn acquire(self: &Arc<Self>) -> Result<QuotaLease, QuotaError> {
+        let limit = self.quota.max_in_flight.unwrap_or(u32::MAX);
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < limit).then_some(n + 1))
+            .map_err(|_| QuotaError::TooManyInFlight { limit })?;
+        let lease = QuotaLease(Arc::clone(self));
+
+        if let Some(rate) = self.quota.rate {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            bucket.tokens = self.refill(&bucket, now);
+            bucket.refilled = now;
+            if bucket.tokens < 1.0 {
+                let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / rate);
+                return Err(QuotaError::RateLimited { retry_after });
+            }
+            bucket.tokens -= 1.0;
+        }
+        Ok(lease)
+    }
+
+    /// Tokens in the bucket at `now`.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let rate = self.quota.rate.unwrap_or(0.0);
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        (bucket.tokens + elapsed * rate).min(self.quota.capacity())
+    }
+
+    /// Returns `true` if nothing distinguishes this state from a new one.
+    fn is_rested(&self, now: Instant) -> bool {
+        let bucket = self.bucket.lock().unwrap();
+        self.in_flight.load(Ordering::Acquire) == 0 && self.refill(&bucket, now) >= self.quota.capacity()
+    }
+}
+
+/// Counts one request against the in-flight quota of its client until dropped.
+#[derive(Debug)]
+pub(crate) struct QuotaLease(Arc<ClientQuota>);
+
+impl Drop for QuotaLease {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
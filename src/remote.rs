@@ -0,0 +1,203 @@
+use crate::crypto::{HashError, Source};
+use serde::Deserialize;
+use std::{
+    io::{self, Read},
+    time::Duration,
+};
+use ureq::{Agent, http::header::LOCATION};
+use url::Url;
+
+/// Settings governing how [`crate::FilePath::Remote`] targets are fetched.
+///
+/// Remote hashing is disabled unless at least one host is allowed, so that a
+/// client cannot turn the orchestrator into an open HTTP proxy.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteConfig {
+    /// Hosts the workers may download from, compared case-insensitively.
+    ///
+    /// Every redirect target is checked against this list as well.
+    pub allowed_hosts: Vec<String>,
+    /// Maximum time allowed to establish the TCP/TLS connection.
+    #[serde(with = "humantime_serde")]
+    pub connect_timeout: Duration,
+    /// Maximum time for the whole download, body included. `None` disables it.
+    #[serde(with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Maximum number of body bytes hashed before the task is aborted.
+    pub max_size: Option<u64>,
+    /// Maximum number of redirects followed. `0` refuses any redirect.
+    pub max_redirects: u32,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            connect_timeout: Duration::from_secs(10),
+            timeout: Some(Duration::from_secs(300)),
+            max_size: None,
+            max_redirects: 5,
+        }
+    }
+}
+
+impl RemoteConfig {
+    /// Returns `true` if remote hashing is enabled at all.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_hosts.is_empty()
+    }
+
+    /// Returns `true` if `host` appears in [`RemoteConfig::allowed_hosts`].
+    pub fn is_host_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+}
+
+/// Blocking HTTP(S) client used by the workers to stream remote files.
+///
+/// The underlying [`Agent`] keeps connections alive between tasks, so a single
+/// fetcher should be shared by the whole worker pool.
+#[derive(Debug)]
+pub struct RemoteFetcher {
+    config: RemoteConfig,
+    agent: Agent,
+}
+
+impl RemoteFetcher {
+    /// Creates a fetcher enforcing the given [`RemoteConfig`].
+    pub fn new(config: RemoteConfig) -> Self {
+        // Redirects are followed by hand so every hop goes through the allowlist.
+        let agent = Agent::config_builder()
+            .http_status_as_error(false)
+            .max_redirects(0)
+            .timeout_connect(Some(config.connect_timeout))
+            .timeout_global(config.timeout)
+            .build()
+            .into();
+
+        Self { config, agent }
+    }
+
+    /// Returns the settings this fetcher enforces.
+    #[inline]
+    pub fn config(&self) -> &RemoteConfig {
+        &self.config
+    }
+
+    /// Starts downloading `url` and returns a reader over the response body,
+    /// sized by its `Content-Length` when the server announced one.
+    ///
+    /// The body is never buffered: bytes are pulled from the socket as the
+    /// hasher consumes them. If [`RemoteConfig::max_size`] is set, reading past
+    /// it fails with an [`io::ErrorKind::FileTooLarge`] error.
+    ///
+    /// # Errors
+    /// - [`HashError::RemoteDisabled`]: no host is allowed by the configuration
+    /// - [`HashError::InvalidUrl`]: the URL is malformed or not HTTP(S)
+    /// - [`HashError::HostNotAllowed`]: the URL or a redirect leaves the allowlist
+    /// - [`HashError::RemoteStatus`]: the server answered with a non-success status
+    /// - [`HashError::TooLarge`]: the announced `Content-Length` exceeds the limit
+    /// - [`HashError::Http`]: the transfer itself failed
+    pub fn open(&self, url: &str) -> Result<Source, HashError> {
+        if !self.config.is_enabled() {
+            return Err(HashError::RemoteDisabled);
+        }
+
+        let mut url = Url::parse(url).map_err(|e| HashError::InvalidUrl(e.to_string()))?;
+        let mut redirects = 0;
+
+        loop {
+            self.check_url(&url)?;
+            let response = self.agent.get(url.as_str()).call()?;
+            let status = response.status();
+
+            if status.is_redirection() {
+                if redirects == self.config.max_redirects {
+                    return Err(HashError::RemoteStatus(status.as_u16()));
+                }
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|l| l.to_str().ok())
+                    .ok_or(HashError::RemoteStatus(status.as_u16()))?;
+                url = url
+                    .join(location)
+                    .map_err(|e| HashError::InvalidUrl(e.to_string()))?;
+                redirects += 1;
+                continue;
+            }
+
+            if !status.is_success() {
+                return Err(HashError::RemoteStatus(status.as_u16()));
+            }
+
+            let body = response.into_body();
+            let len = body.content_length();
+            if let (Some(limit), Some(len)) = (self.config.max_size, len)
+                && len > limit
+            {
+                return Err(HashError::TooLarge(limit));
+            }
+
+            let reader = body.into_reader();
+            let reader: Box<dyn Read + Send> = match self.config.max_size {
+                Some(limit) => Box::new(Limited::new(reader, limit)),
+                None => Box::new(reader),
+            };
+            return Ok(Source { reader, len });
+        }
+    }
+
+    /// Rejects URLs that are not HTTP(S) or whose host is not allowed.
+    fn check_url(&self, url: &Url) -> Result<(), HashError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(HashError::InvalidUrl(format!(
+                "unsupported scheme {}",
+                url.scheme()
+            )));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| HashError::InvalidUrl(String::from("missing host")))?;
+        if !self.config.is_host_allowed(host) {
+            return Err(HashError::HostNotAllowed(host.to_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// A reader failing once more than `limit` bytes have been read.
+struct Limited<R> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R> Limited<R> {
+    fn new(inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            limit,
+        }
+    }
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        if count as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("remote body exceeds {} bytes", self.limit),
+            ));
+        }
+        self.remaining -= count as u64;
+        Ok(count)
+    }
+}
@@ -0,0 +1,167 @@
+use crate::crypto::HashError;
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Restrictions on the local files clients may hash, the `[sandbox]` section
+/// of a [`crate::config::ServerConfig`].
+///
+/// ```toml
+/// [sandbox]
+/// roots = ["/srv/artifacts", "/var/lib/builds"]
+/// allow_special_files = false
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// Directories the requested files must lie in, after resolving symlinks.
+    ///
+    /// An empty list allows any file the server process can read.
+    pub roots: Vec<PathBuf>,
+    /// Also hash devices, FIFOs and sockets, which may never reach the end
+    /// of their input. Refused by default.
+    pub allow_special_files: bool,
+}
+
+/// The policy applied to every [`crate::FilePath::Local`] before it is opened.
+///
+/// Built from a [`SandboxConfig`] once, when the server starts, so that the
+/// roots are canonicalized a single time.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    roots: Vec<PathBuf>,
+    allow_special_files: bool,
+}
+
+impl Sandbox {
+    /// Resolves the configured roots.
+    ///
+    /// # Errors
+    /// Returns an error if a root does not exist or cannot be resolved.
+    pub fn new(config: &SandboxConfig) -> io::Result<Self> {
+        let roots = config
+            .roots
+            .iter()
+            .map(|root| {
+                fs::canonicalize(root).map_err(|e| {
+                    io::Error::new(e.kind(), format!("sandbox root {}: {}", root.display(), e))
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            roots,
+            allow_special_files: config.allow_special_files,
+        })
+    }
+
+    /// Returns `true` if no root restricts the files that may be hashed.
+    pub fn is_unrestricted(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Opens `path` for reading if the policy allows it.
+    ///
+    /// The path is canonicalized, so `..` components and symlinks are
+    /// resolved before it is compared with the roots. The resolved path is
+    /// then opened without following a symlink in its last component and,
+    /// unless special files are allowed, without blocking on a FIFO. Whether
+    /// it is a regular file is decided on the opened file itself, which must
+    /// also still be the file the resolved path names.
+    ///
+    /// # Errors
+    /// Returns [`HashError::PathNotAllowed`] if the file lies outside the
+    /// roots or is not a regular file, and [`HashError::Io`] if it cannot be
+    /// opened. A missing file outside the roots is reported as not allowed,
+    /// so that clients cannot probe for the existence of files they may not read.
+    pub fn open(&self, path: &str) -> Result<fs::File, HashError> {
+        let changed = || HashError::PathNotAllowed(format!("{} changed while being opened", path));
+        let canonical = match fs::canonicalize(path) {
+            Ok(canonical) => canonical,
+            Err(_) if !self.contains(&lexical_absolute(Path::new(path))?) => {
+                return Err(HashError::PathNotAllowed(format!("{} is outside the allowed roots", path)));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !self.contains(&canonical) {
+            return Err(HashError::PathNotAllowed(format!("{} is outside the allowed roots", path)));
+        }
+
+        let file = match open_no_follow(&canonical, !self.allow_special_files) {
+            Ok(file) => file,
+            Err(e) if is_symlink_error(&e) => return Err(changed()),
+            Err(e) => return Err(e.into()),
+        };
+        let opened = file.metadata()?;
+        if !self.allow_special_files && !opened.is_file() {
+            return Err(HashError::PathNotAllowed(format!("{} is not a regular file", path)));
+        }
+        if !same_file(&opened, &fs::symlink_metadata(&canonical)?) {
+            return Err(changed());
+        }
+        Ok(file)
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.roots.is_empty() || self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
+/// Makes `path` absolute and folds its `.` and `..` components without
+/// touching the filesystem.
+fn lexical_absolute(path: &Path) -> io::Result<PathBuf> {
+    let mut absolute = fs::canonicalize(".")?;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => absolute.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            Component::Normal(part) => absolute.push(part),
+        }
+    }
+    Ok(absolute)
+}
+
+/// Opens `path` for reading, failing if its last component is a symlink.
+///
+/// With `nonblocking`, opening a FIFO returns at once instead of waiting for
+/// a writer. Reads from a regular file are not affected by the flag.
+#[cfg(unix)]
+fn open_no_follow(path: &Path, nonblocking: bool) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut flags = libc::O_NOFOLLOW;
+    if nonblocking {
+        flags |= libc::O_NONBLOCK;
+    }
+    fs::OpenOptions::new().read(true).custom_flags(flags).open(path)
+}
+
+#[cfg(not(unix))]
+fn open_no_follow(path: &Path, _nonblocking: bool) -> io::Result<fs::File> {
+    fs::File::open(path)
+}
+
+#[cfg(unix)]
+fn is_symlink_error(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ELOOP)
+}
+
+#[cfg(not(unix))]
+fn is_symlink_error(_e: &io::Error) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.file_type() == b.file_type() && a.len() == b.len()
+}
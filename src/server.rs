@@ -0,0 +1,982 @@
+use crate::{
+    HashAlgorithms, ServerMetrics,
+    auth::AuthConfig,
+    config::ServerConfig,
+    metrics::serve_metrics,
+    constants::{
+        ACCEPT_BACKOFF, CONTROL_QUEUE_DEPTH, MAX_CONCURRENT_REFUSALS, PROTOCOL_VERSION,
+        REFUSAL_TIMEOUT,
+    },
+    sandbox::Sandbox,
+    protocol::{
+        FailureReason, FrameConfig, ProtocolError, ProtocolMessage, RequestId, ServerError, TaskRequest,
+        TaskResponse, Welcome, read_protocol_with, write_protocol,
+    },
+    queue::{Admission, Flow, WorkQueue},
+    quota::{ClientKey, ClientQuota, Quotas},
+    workers::{WorkItem, start_worker_pool},
+};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, atomic::Ordering};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{OwnedSemaphorePermit, Semaphore, mpsc},
+    time::{Instant, timeout},
+};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream, unix::UCred};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{Instrument, Span, debug, error, field, info, info_span, warn};
+
+/// State shared by every connection of a server.
+#[derive(Clone)]
+struct Shared {
+    queue: Arc<WorkQueue>,
+    quotas: Arc<Quotas>,
+    slots: Arc<ConnectionSlots>,
+    /// Bounds the refusals in progress to [`MAX_CONCURRENT_REFUSALS`].
+    refusals: Arc<Semaphore>,
+    metrics: Arc<ServerMetrics>,
+    config: Arc<ServerConfig>,
+    tls: Option<TlsAcceptor>,
+    shutdown: CancellationToken,
+    /// Cancelled once the drain deadline expires, to close whatever is left.
+    force_close: CancellationToken,
+}
+
+impl Shared {
+    /// Reserves a slot for a new connection from `ip`, if any.
+    ///
+    /// A connection over a limit gets a [`Refusal`] while fewer than
+    /// [`MAX_CONCURRENT_REFUSALS`] are in progress, and `None` past that, in
+    /// which case it should be closed right away.
+    fn connection_slot(&self, ip: Option<IpAddr>) -> Option<Result<ConnectionSlot, Refusal>> {
+        let error = match self.slots.acquire(ip) {
+            Ok(slot) => return Some(Ok(slot)),
+            Err(error) => error,
+        };
+        match error {
+            ServerError::TooManyConnections => &self.metrics.refused_connections,
+            _ => &self.metrics.refused_peer_connections,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+
+        match Arc::clone(&self.refusals).try_acquire_owned() {
+            Ok(permit) => Some(Err(Refusal {
+                error,
+                _permit: permit,
+            })),
+            Err(_) => {
+                debug!(error = %error, "too many refusals in progress, closing connection");
+                None
+            }
+        }
+    }
+}
+
+/// A connection over a limit, to be told why before it is closed.
+struct Refusal {
+    error: ServerError,
+    _permit: OwnedSemaphorePermit,
+}
+
+/// The cancellation tokens of the requests a connection has in flight.
+///
+/// Every token is a child of `closed`, so that all the tasks of a connection
+/// can be abandoned at once when its client goes away.
+#[derive(Default)]
+struct ConnectionTasks {
+    ids: Mutex<TaskIds>,
+    closed: CancellationToken,
+}
+
+#[derive(Default)]
+struct TaskIds {
+    tokens: HashMap<RequestId, CancellationToken>,
+    /// Refusals of requests reusing an id still in flight, not written yet.
+    duplicates: HashMap<RequestId, usize>,
+}
+
+impl ConnectionTasks {
+    /// Returns the token of a newly dispatched request, or `None` if its id
+    /// is already in flight, in which case the request must be refused.
+    fn register(&self, id: RequestId) -> Option<CancellationToken> {
+        let mut ids = self.ids.lock().unwrap();
+        if ids.tokens.contains_key(&id) {
+            *ids.duplicates.entry(id).or_default() += 1;
+            return None;
+        }
+        let token = self.closed.child_token();
+        ids.tokens.insert(id, token.clone());
+        Some(token)
+    }
+
+    /// Cancels the request with this id, if it is still pending.
+    fn cancel(&self, id: RequestId) -> bool {
+        match self.ids.lock().unwrap().tokens.remove(&id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets a request once its response was written.
+    ///
+    /// The refusal of a duplicate carries the same id as the request in
+    /// flight: whichever of the two is written first only settles the
+    /// refusal, so that the request stays cancellable until both are.
+    fn finish(&self, id: RequestId) {
+        let mut ids = self.ids.lock().unwrap();
+        match ids.duplicates.get_mut(&id) {
+            Some(1) => {
+                ids.duplicates.remove(&id);
+            }
+            Some(count) => *count -= 1,
+            None => {
+                ids.tokens.remove(&id);
+            }
+        }
+    }
+
+    /// Cancels every pending request of the connection.
+    fn cancel_all(&self) {
+        self.closed.cancel();
+    }
+}
+
+/// Counts the connections being served, in total and by IP address, to
+/// enforce [`ServerConfig::max_connections`] and
+/// [`ServerConfig::max_connections_per_ip`].
+struct ConnectionSlots {
+    max: usize,
+    max_per_ip: Option<usize>,
+    counts: Mutex<SlotCounts>,
+}
+
+#[derive(Default)]
+struct SlotCounts {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+impl ConnectionSlots {
+    fn new(config: &ServerConfig) -> Self {
+        Self {
+            max: config.max_connections,
+            max_per_ip: config.max_connections_per_ip,
+            counts: Mutex::default(),
+        }
+    }
+
+    /// Reserves a slot for a new connection from `ip`, if any, until the
+    /// returned guard is dropped.
+    fn acquire(self: &Arc<Self>, ip: Option<IpAddr>) -> Result<ConnectionSlot, ServerError> {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.total >= self.max {
+            return Err(ServerError::TooManyConnections);
+        }
+        if let Some(ip) = ip {
+            let open = counts.by_ip.entry(ip).or_default();
+            if self.max_per_ip.is_some_and(|max| *open >= max) {
+                return Err(ServerError::TooManyPeerConnections);
+            }
+            *open += 1;
+        }
+        counts.total += 1;
+        Ok(ConnectionSlot {
+            slots: Arc::clone(self),
+            ip,
+        })
+    }
+}
+
+/// A connection counted by [`ConnectionSlots`], released when dropped.
+struct ConnectionSlot {
+    slots: Arc<ConnectionSlots>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.slots.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(ip) = self.ip
+            && let Some(open) = counts.by_ip.get_mut(&ip)
+        {
+            *open -= 1;
+            if *open == 0 {
+                counts.by_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Outcome of a graceful shutdown, returned by [`run_server_on`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// Connections that were open when the shutdown began.
+    pub connections: u64,
+    /// Accepted tasks that completed while draining.
+    pub completed_tasks: u64,
+    /// Accepted tasks still queued or running when the deadline expired.
+    pub abandoned_tasks: u64,
+    /// `true` if [`ServerConfig::shutdown_timeout`] expired before draining ended.
+    pub timed_out: bool,
+    /// Time spent draining.
+    pub elapsed: Duration,
+}
+
+/// Start the server loop on an existing TCP listener.
+///
+/// This function accepts incoming connections, updates connection metrics, and delegates
+/// work items to a worker pool sized and configured by `config` (its `bind` address is
+/// ignored). It returns when the underlying I/O fails or once `shutdown` is cancelled.
+///
+/// An invalid `config` (see [`ServerConfig::validate`]) makes this function
+/// fail immediately with [`std::io::ErrorKind::InvalidInput`].
+///
+/// If [`ServerConfig::tls`] is set, every connection must complete a TLS
+/// handshake before the protocol handshake; an unusable certificate or key
+/// makes this function fail immediately with [`std::io::ErrorKind::InvalidInput`].
+///
+/// If [`ServerConfig::unix`] is set, local clients are also accepted on that
+/// socket. Peers whose credentials are not allowed receive a
+/// [`ServerError::Forbidden`] frame and are disconnected.
+///
+/// # Shutdown
+/// Cancelling `shutdown` stops accepting connections and reading requests. Tasks
+/// already accepted keep running for up to [`ServerConfig::shutdown_timeout`] and
+/// their responses are delivered. Every client then receives a
+/// [`ServerError::ShuttingDown`] frame before its connection is closed.
+pub async fn run_server_on(
+    listener: TcpListener,
+    config: ServerConfig,
+    shutdown: CancellationToken,
+) -> tokio::io::Result<ShutdownSummary> {
+    serve(Some(listener), config, shutdown).await
+}
+
+/// Bind to the configured address and start the server with a worker pool.
+///
+/// This function creates a TCP listener on [`ServerConfig::bind`], unless
+/// [`ServerConfig::tcp`] is disabled, and delegates all incoming work to the
+/// worker pool managed by [`run_server_on`], until `shutdown` is cancelled.
+pub async fn run_server(
+    config: ServerConfig,
+    shutdown: CancellationToken,
+) -> tokio::io::Result<ShutdownSummary> {
+    let listener = if config.tcp {
+        let listener = TcpListener::bind(&config.bind).await?;
+        info!(addr = %listener.local_addr()?, "server listening");
+        Some(listener)
+    } else {
+        None
+    };
+    serve(listener, config, shutdown).await
+}
+
+/// Runs the accept loop over the TCP listener, if any, and the configured
+/// Unix socket, if any, then drains the connections.
+async fn serve(
+    listener: Option<TcpListener>,
+    config: ServerConfig,
+    shutdown: CancellationToken,
+) -> tokio::io::Result<ShutdownSummary> {
+    // The fields are public, so the configuration may not have been checked.
+    config
+        .validate()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let tls = match &config.tls {
+        Some(tls) => Some(tls.acceptor().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?),
+        None => None,
+    };
+    let sandbox = Sandbox::new(&config.sandbox)?;
+    if sandbox.is_unrestricted() {
+        warn!("no sandbox roots configured, clients may hash any file the server can read");
+    }
+    let listeners = Listeners {
+        tcp: listener,
+        #[cfg(unix)]
+        local: match &config.unix {
+            Some(unix) => {
+                let local = unix.bind()?;
+                info!(path = %unix.path.display(), "local socket listening");
+                Some(local)
+            }
+            None => None,
+        },
+    };
+
+    let metrics = Arc::new(ServerMetrics::new());
+    let queue = Arc::new(WorkQueue::new(
+        config.queue_depth,
+        config.admission.policy,
+        &config.scheduling,
+    ));
+
+    if let Some(bind) = &config.metrics_bind {
+        let metrics_listener = TcpListener::bind(bind).await?;
+        info!(addr = %metrics_listener.local_addr()?, "metrics endpoint listening");
+        tokio::spawn(serve_metrics(
+            metrics_listener,
+            Arc::clone(&metrics),
+            shutdown.clone(),
+        ));
+    }
+
+    start_worker_pool(Arc::clone(&queue), &config, sandbox, Arc::clone(&metrics)).await;
+    let config = Arc::new(config);
+    let connections = TaskTracker::new();
+    let force_close = CancellationToken::new();
+    let shared = Shared {
+        queue: Arc::clone(&queue),
+        quotas: Arc::new(Quotas::new(config.quotas.clone())),
+        slots: Arc::new(ConnectionSlots::new(&config)),
+        refusals: Arc::new(Semaphore::new(MAX_CONCURRENT_REFUSALS)),
+        metrics: Arc::clone(&metrics),
+        config: Arc::clone(&config),
+        tls,
+        shutdown: shutdown.clone(),
+        force_close: force_close.clone(),
+    };
+
+    loop {
+        let accepted = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            accepted = listeners.accept() => accepted,
+        };
+        let incoming = match accepted {
+            Ok(incoming) => incoming,
+            // Running out of descriptors is what a connection flood causes:
+            // wait for some to be released instead of stopping the server.
+            Err(e) if is_transient_accept_error(&e) => {
+                warn!(error = %e, "failed to accept a connection");
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(ACCEPT_BACKOFF) => continue,
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        match incoming {
+            Incoming::Tcp(socket, addr) => {
+                let Some(slot) = shared.connection_slot(Some(addr.ip())) else {
+                    continue;
+                };
+                let connection = accept_connection(socket, addr, slot, shared.clone());
+                let span = info_span!("connection", peer = %addr, identity = field::Empty);
+                connections.spawn(connection.instrument(span));
+            }
+            #[cfg(unix)]
+            Incoming::Unix(socket) => {
+                let cred = socket.peer_cred();
+                let peer = match &cred {
+                    Ok(cred) => format!("uid={} gid={} pid={}", cred.uid(), cred.gid(), cred.pid().unwrap_or(0)),
+                    Err(_) => String::from("unknown"),
+                };
+                let Some(slot) = shared.connection_slot(None) else {
+                    continue;
+                };
+                let connection = accept_local(socket, cred, slot, shared.clone());
+                let span = info_span!("connection", peer = %peer, identity = field::Empty);
+                connections.spawn(connection.instrument(span));
+            }
+        }
+    }
+
+    drop(listeners);
+    #[cfg(unix)]
+    if let Some(unix) = &config.unix {
+        let _ = std::fs::remove_file(&unix.path);
+    }
+    drop(shared);
+    connections.close();
+
+    let started = Instant::now();
+    let open = metrics.active_connections.load(Ordering::SeqCst);
+    let pending = metrics.pending_tasks.load(Ordering::SeqCst);
+    info!(pending_tasks = pending, connections = open, "shutting down, draining tasks");
+
+    let timed_out = timeout(config.shutdown_timeout, connections.wait())
+        .await
+        .is_err();
+    let abandoned = if timed_out {
+        let abandoned = metrics.pending_tasks.load(Ordering::SeqCst);
+        force_close.cancel();
+        connections.wait().await;
+        abandoned
+    } else {
+        0
+    };
+
+    // Workers exit once the tasks left by forcibly closed connections are skipped.
+    queue.close();
+    if timed_out {
+        warn!(abandoned_tasks = abandoned, "drain deadline expired");
+    }
+    Ok(ShutdownSummary {
+        connections: open,
+        completed_tasks: pending.saturating_sub(abandoned),
+        abandoned_tasks: abandoned,
+        timed_out,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// The sockets a server accepts connections on.
+struct Listeners {
+    tcp: Option<TcpListener>,
+    #[cfg(unix)]
+    local: Option<UnixListener>,
+}
+
+/// A connection accepted by one of the [`Listeners`].
+enum Incoming {
+    Tcp(TcpStream, SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Listeners {
+    /// Waits for the next connection on whichever listener receives one first.
+    async fn accept(&self) -> io::Result<Incoming> {
+        let tcp = async {
+            match &self.tcp {
+                Some(listener) => listener
+                    .accept()
+                    .await
+                    .map(|(socket, addr)| Incoming::Tcp(socket, addr)),
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(unix)]
+        let local = async {
+            match &self.local {
+                Some(listener) => listener.accept().await.map(|(socket, _)| Incoming::Unix(socket)),
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let local = std::future::pending();
+
+        tokio::select! {
+            accepted = tcp => accepted,
+            accepted = local => accepted,
+        }
+    }
+}
+
+/// Returns `true` if accepting may succeed again once the server waited.
+fn is_transient_accept_error(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if matches!(
+        e.raw_os_error(),
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+    ) {
+        return true;
+    }
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset | io::ErrorKind::Interrupted
+    )
+}
+
+/// Checks the credentials of a local peer and serves it if they are allowed.
+///
+/// A refused peer receives a [`ServerError::Forbidden`] frame.
+#[cfg(unix)]
+async fn accept_local(
+    mut socket: UnixStream,
+    cred: io::Result<UCred>,
+    slot: Result<ConnectionSlot, Refusal>,
+    shared: Shared,
+) {
+    let allowed = match (&shared.config.unix, &cred) {
+        (Some(unix), Ok(cred)) => unix.allows(cred),
+        (None, Ok(_)) => true,
+        (_, Err(e)) => {
+            warn!(error = %e, "cannot read peer credentials");
+            false
+        }
+    };
+    if let (true, Ok(cred)) = (allowed, cred) {
+        return serve_or_refuse(socket, slot, shared, ClientKey::LocalUser(cred.uid())).await;
+    }
+
+    warn!("peer credentials not allowed, refusing connection");
+    shared
+        .metrics
+        .record_protocol_error(&ProtocolError::Refused(ServerError::Forbidden));
+    let _ = write_protocol(&mut socket, &ProtocolMessage::Error(ServerError::Forbidden)).await;
+}
+
+/// Completes the TLS handshake, if enabled, and serves the connection.
+///
+/// A connection that did not get a `slot` still completes the TLS handshake,
+/// within [`REFUSAL_TIMEOUT`], so that it can be told why it is refused.
+async fn accept_connection(
+    socket: TcpStream,
+    addr: SocketAddr,
+    slot: Result<ConnectionSlot, Refusal>,
+    shared: Shared,
+) {
+    let peer = ClientKey::Address(addr.ip());
+    let Some(acceptor) = shared.tls.clone() else {
+        return serve_or_refuse(socket, slot, shared, peer).await;
+    };
+
+    let deadline = match slot {
+        Ok(_) => shared.config.read_timeout,
+        Err(_) => REFUSAL_TIMEOUT,
+    };
+    let handshake = tokio::select! {
+        _ = shared.shutdown.cancelled() => return,
+        result = timeout(deadline, acceptor.accept(socket)) => result,
+    };
+    match handshake {
+        Ok(Ok(stream)) => serve_or_refuse(stream, slot, shared, peer).await,
+        Ok(Err(e)) => {
+            warn!(error = %e, "TLS handshake failed");
+            shared.metrics.record_protocol_error(&ProtocolError::Io(e));
+        }
+        Err(e) => {
+            warn!("TLS handshake timed out");
+            shared.metrics.record_protocol_error(&ProtocolError::TimeOutError(e));
+        }
+    }
+}
+
+/// Serves the connection if it got a slot, or sends it the refusal.
+async fn serve_or_refuse<S>(
+    socket: S,
+    slot: Result<ConnectionSlot, Refusal>,
+    shared: Shared,
+    peer: ClientKey,
+) where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    match slot {
+        Ok(_slot) => handle_connection(socket, shared, peer).await,
+        Err(refusal) => refuse(socket, refusal, &shared).await,
+    }
+}
+
+/// Sends a final error frame to a client that will not be served.
+///
+/// The client's `Hello` is left unread: closing right away could then reset
+/// the connection and discard the frame, so the socket is drained until the
+/// client hangs up or [`REFUSAL_TIMEOUT`] expires.
+async fn refuse<S>(mut socket: S, refusal: Refusal, shared: &Shared)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let Refusal { error, _permit } = refusal;
+    warn!(error = %error, "connection limit reached, refusing connection");
+    shared
+        .metrics
+        .record_protocol_error(&ProtocolError::Refused(error.clone()));
+
+    let _ = timeout(REFUSAL_TIMEOUT, async {
+        write_protocol(&mut socket, &ProtocolMessage::Error(error)).await?;
+        socket.shutdown().await?;
+        let mut discarded = [0u8; 1024];
+        while socket.read(&mut discarded).await? > 0 {}
+        Ok::<_, ProtocolError>(())
+    })
+    .await;
+}
+
+/// Serves a single client from handshake to disconnection.
+///
+/// The socket is split in two: this task keeps reading requests and
+/// dispatching them to the worker pool, while a writer task sends responses
+/// back in the order the workers finish them. At most
+/// [`ServerConfig::max_in_flight_requests`] requests may be pending at once.
+///
+/// Once `shutdown` is cancelled no further request is read, and the writer
+/// exits after the pending responses were sent or when `force_close` is
+/// cancelled, whichever comes first.
+///
+/// When the client disconnects, or once nothing can be written back to it,
+/// its pending tasks are cancelled so that workers do not hash for nobody.
+/// A worker unable to queue a response closes the connection the same way,
+/// so that the client notices instead of waiting for it.
+///
+/// Requests count against the quota of the client, recognized by its
+/// authenticated identity or else by `peer`, across all its connections, and
+/// are scheduled fairly against those of other clients.
+///
+/// The caller runs this inside a `connection` span carrying the peer address,
+/// so every event of the connection and of its tasks can be correlated.
+async fn handle_connection<S>(mut socket: S, shared: Shared, peer: ClientKey)
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let Shared {
+        metrics,
+        config,
+        shutdown,
+        force_close,
+        ..
+    } = &shared;
+    let active = metrics.active_connections.fetch_add(1, Ordering::SeqCst) + 1;
+    info!(active, "client connected");
+
+    // The handshake must complete promptly: clients may only idle once in.
+    let limits = FrameConfig {
+        idle_timeout: Some(config.read_timeout),
+        ..config.frame_config()
+    };
+    let handshake = tokio::select! {
+        _ = shutdown.cancelled() => None,
+        result = server_handshake(&mut socket, &limits, config.auth.as_ref()) => Some(result),
+    };
+    let Some(handshake) = handshake else {
+        debug!("shutting down, handshake abandoned");
+        let goodbye = ProtocolMessage::Error(ServerError::ShuttingDown);
+        let _ = timeout(config.read_timeout, write_protocol(&mut socket, &goodbye)).await;
+        metrics.active_connections.fetch_sub(1, Ordering::SeqCst);
+        return;
+    };
+    let client = match handshake {
+        Ok(Some(identity)) => {
+            Span::current().record("identity", identity.as_str());
+            info!("client authenticated");
+            ClientKey::Identity(identity)
+        }
+        Ok(None) => peer,
+        Err(e) => {
+            metrics.record_protocol_error(&e);
+            warn!(error = %e, "handshake failed");
+            metrics.active_connections.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+    };
+    let weight = config.scheduling.weight_of(&client, &config.quotas);
+    let flow = Arc::new(Flow::new(client.clone(), weight));
+    let quota = shared.quotas.client(client);
+
+    let (mut reader, writer) = tokio::io::split(socket);
+    let in_flight = Arc::new(Semaphore::new(config.max_in_flight_requests));
+    let tasks = Arc::new(ConnectionTasks::default());
+    let hang_up = force_close.child_token();
+    let (out_tx, out_rx) = mpsc::channel::<ProtocolMessage>(config.max_in_flight_requests);
+    let (control_tx, control_rx) = mpsc::channel::<ProtocolMessage>(CONTROL_QUEUE_DEPTH);
+    let writer = write_responses(
+        writer,
+        out_rx,
+        control_rx,
+        Arc::clone(&in_flight),
+        Arc::clone(&tasks),
+        shutdown.clone(),
+        hang_up.clone(),
+    );
+    let writer_task = tokio::spawn(writer.in_current_span());
+
+    let outbound = Outbound {
+        responses: out_tx,
+        control: control_tx,
+        hang_up,
+    };
+    let requests = read_requests(&mut reader, &shared, &in_flight, &tasks, &quota, &flow, &outbound);
+    tokio::select! {
+        _ = shutdown.cancelled() => {}
+        _ = outbound.hang_up.cancelled() => {}
+        _ = requests => tasks.cancel_all(),
+    }
+
+    // The writer exits once every pending response has been flushed.
+    drop(outbound);
+    let _ = writer_task.await;
+
+    metrics.active_connections.fetch_sub(1, Ordering::SeqCst);
+    info!("client disconnected");
+}
+
+/// The queues the writer of a connection drains onto its socket.
+struct Outbound {
+    /// Task responses only. A response is queued once it holds an in-flight
+    /// permit, so the queue has room for every one of them.
+    responses: mpsc::Sender<ProtocolMessage>,
+    /// Heartbeats and progress frames, dropped when the queue is full so
+    /// that they can never crowd out a response.
+    control: mpsc::Sender<ProtocolMessage>,
+    /// Closes the connection at once, e.g. when a response could not be
+    /// queued in time.
+    hang_up: CancellationToken,
+}
+
+/// Reads requests from a client and dispatches them until the connection fails.
+///
+/// A client silent for [`ServerConfig::heartbeat_interval`] is sent a
+/// [`ProtocolMessage::Ping`]; one that sent no frame at all, answers
+/// included, for [`ServerConfig::idle_timeout`] is disconnected.
+///
+/// Requests exceeding the client's `quota` are answered with
+/// [`TaskResponse::QuotaExceeded`] without reaching the queue; the others
+/// are queued on behalf of the client of `flow`.
+async fn read_requests<R>(
+    reader: &mut R,
+    shared: &Shared,
+    in_flight: &Arc<Semaphore>,
+    tasks: &ConnectionTasks,
+    quota: &Arc<ClientQuota>,
+    flow: &Arc<Flow>,
+    outbound: &Outbound,
+) where
+    R: AsyncRead + Unpin,
+{
+    let Shared {
+        queue,
+        metrics,
+        config,
+        ..
+    } = shared;
+    let limits = config.frame_config();
+    let mut last_frame = Instant::now();
+    let mut pings = 0;
+    loop {
+        let packet = match read_protocol_with(reader, &limits).await {
+            Ok(p) => p,
+            Err(ProtocolError::Idle) if last_frame.elapsed() < config.idle_timeout => {
+                pings += 1;
+                if !send_control(&outbound.control, ProtocolMessage::Ping(pings)) {
+                    return;
+                }
+                continue;
+            }
+            Err(ProtocolError::Idle) => {
+                metrics.record_protocol_error(&ProtocolError::Idle);
+                info!("closing idle connection");
+                return;
+            }
+            Err(e) => {
+                metrics.record_protocol_error(&e);
+                if e.is_disconnect() {
+                    debug!("connection closed by peer");
+                } else {
+                    warn!(error = %e, kind = e.kind(), "protocol error");
+                }
+                return;
+            }
+        };
+        last_frame = Instant::now();
+        let task = match packet {
+            ProtocolMessage::TaskRequest(t) => t,
+            ProtocolMessage::CancelTask(id) => {
+                if tasks.cancel(id) {
+                    debug!(id, "task cancelled by client");
+                }
+                continue;
+            }
+            ProtocolMessage::Ping(token) => {
+                if !send_control(&outbound.control, ProtocolMessage::Pong(token)) {
+                    return;
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        // The permit is given back by the writer once the response is sent.
+        match Arc::clone(in_flight).acquire_owned().await {
+            Ok(permit) => permit.forget(),
+            Err(_) => return,
+        }
+
+        match task {
+            TaskRequest::HashPacket { id, packet, priority } => {
+                let Some(cancel) = tasks.register(id) else {
+                    metrics.record_failure(FailureReason::InvalidRequest);
+                    debug!(id, "request id already in flight, task refused");
+                    let response = TaskResponse::Failed {
+                        id,
+                        reason: FailureReason::InvalidRequest,
+                        message: Some(format!("Request id {} is already in flight", id)),
+                    };
+                    if outbound.responses.send(ProtocolMessage::TaskResponse(response)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                };
+                let lease = match quota.acquire() {
+                    Ok(lease) => lease,
+                    Err(error) => {
+                        metrics.record_quota_rejection(&error);
+                        debug!(id, error = %error, "quota exceeded, task refused");
+                        let response = TaskResponse::QuotaExceeded { id, error };
+                        let response = ProtocolMessage::TaskResponse(response);
+                        if outbound.responses.send(response).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                let item = WorkItem::new(id, packet, outbound.responses.clone(), cancel)
+                    .with_progress(outbound.control.clone())
+                    .with_hang_up(outbound.hang_up.clone())
+                    .with_lease(lease)
+                    .with_flow(Arc::clone(flow))
+                    .with_priority(priority);
+                let retry_after = config.admission.retry_after;
+                // Counting only admitted tasks keeps the counters exact if a
+                // shutdown cancels this future while the queue is full.
+                match queue.submit(item).await {
+                    Admission::Queued => {
+                        metrics.pending_tasks.fetch_add(1, Ordering::SeqCst);
+                        metrics.queued_tasks.fetch_add(1, Ordering::SeqCst);
+                    }
+                    // The shed task may belong to another connection, whose
+                    // outbound queue must not hold this one up.
+                    Admission::Shed(oldest) => {
+                        metrics.busy_tasks.fetch_add(1, Ordering::Relaxed);
+                        tokio::spawn(oldest.reject(retry_after, queue.len()));
+                    }
+                    Admission::Full(item) => {
+                        metrics.busy_tasks.fetch_add(1, Ordering::Relaxed);
+                        item.reject(retry_after, queue.len()).await;
+                    }
+                    Admission::Closed(item) => {
+                        error!("worker pool is gone, dropping client");
+                        item.reject(retry_after, 0).await;
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Queues a heartbeat frame, dropping it if the control queue is full.
+///
+/// Returns `false` once the writer of the connection is gone.
+fn send_control(control: &mpsc::Sender<ProtocolMessage>, message: ProtocolMessage) -> bool {
+    match control.try_send(message) {
+        Ok(()) => true,
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            debug!("control queue is full, heartbeat dropped");
+            true
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => false,
+    }
+}
+
+/// Drains the outbound queues of a connection onto its socket.
+///
+/// Each written [`crate::protocol::TaskResponse`] returns one in-flight
+/// permit to the reader side of the connection and retires the cancellation
+/// token of its request. During a shutdown, a final
+/// [`ServerError::ShuttingDown`] frame is written once the queue is drained.
+async fn write_responses<W>(
+    mut writer: W,
+    mut outbound: mpsc::Receiver<ProtocolMessage>,
+    mut control: mpsc::Receiver<ProtocolMessage>,
+    in_flight: Arc<Semaphore>,
+    tasks: Arc<ConnectionTasks>,
+    shutdown: CancellationToken,
+    force_close: CancellationToken,
+) where
+    W: AsyncWrite + Unpin,
+{
+    loop {
+        let message = tokio::select! {
+            _ = force_close.cancelled() => break,
+            message = outbound.recv() => match message {
+                Some(message) => message,
+                None => break,
+            },
+            Some(message) = control.recv() => message,
+        };
+        match write_protocol(&mut writer, &message).await {
+            Ok(()) => {}
+            Err(ProtocolError::Io(e)) => {
+                debug!(error = %e, "failed to write to the socket");
+                break;
+            }
+            Err(e) => {
+                error!(error = %e, "invalid response from worker");
+                continue;
+            }
+        }
+
+        if let ProtocolMessage::TaskResponse(response) = message {
+            tasks.finish(response.id());
+            in_flight.add_permits(1);
+        }
+    }
+    // Wake up a reader blocked on a permit so that it notices the failure,
+    // and stop the tasks whose results can no longer be delivered.
+    in_flight.close();
+    tasks.cancel_all();
+
+    if shutdown.is_cancelled() {
+        let _ = write_protocol(&mut writer, &ProtocolMessage::Error(ServerError::ShuttingDown)).await;
+    }
+}
+
+/// Performs the server side of the `Hello`/`Welcome` handshake.
+///
+/// The first frame of a connection must be a [`crate::protocol::Hello`] carrying
+/// [`PROTOCOL_VERSION`]. When `auth` is set, the client must then answer a
+/// [`Challenge`](crate::protocol::Challenge) with the key of one of its
+/// identities. On success a [`Welcome`] describing the server's capabilities,
+/// including the frame size allowed by `limits`, is written back and the
+/// authenticated identity, if any, is returned. Otherwise a [`ServerError`]
+/// frame is sent and the refusal is returned so that the caller can close the
+/// connection.
+///
+/// # Errors
+/// Returns [`ProtocolError::Refused`] when the client was turned away, or
+/// any I/O or decoding error raised while exchanging the frames.
+async fn server_handshake<S>(
+    socket: &mut S,
+    limits: &FrameConfig,
+    auth: Option<&AuthConfig>,
+) -> Result<Option<String>, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let welcome = ProtocolMessage::Welcome(Welcome {
+        version: PROTOCOL_VERSION,
+        algorithms: HashAlgorithms::IMPLEMENTED.to_vec(),
+        max_packet_size: limits.max_packet_size as u32,
+    });
+
+    let refusal = match read_protocol_with(socket, limits).await? {
+        ProtocolMessage::Hello(hello) if hello.version == PROTOCOL_VERSION => {
+            let Some(auth) = auth else {
+                write_protocol(socket, &welcome).await?;
+                return Ok(None);
+            };
+
+            let challenge = auth.challenge();
+            write_protocol(socket, &ProtocolMessage::Challenge(challenge.clone())).await?;
+            match read_protocol_with(socket, limits).await? {
+                ProtocolMessage::Authenticate(answer) if auth.verify(&challenge, &answer) => {
+                    write_protocol(socket, &welcome).await?;
+                    return Ok(Some(answer.identity));
+                }
+                ProtocolMessage::Authenticate(answer) => {
+                    warn!(identity = %answer.identity, "authentication failed");
+                    ServerError::AuthenticationFailed
+                }
+                _ => ServerError::AuthenticationFailed,
+            }
+        }
+        ProtocolMessage::Hello(hello) => ServerError::VersionMismatch {
+            server: PROTOCOL_VERSION,
+            client: hello.version,
+        },
+        _ => ServerError::HandshakeRequired,
+    };
+
+    write_protocol(socket, &ProtocolMessage::Error(refusal.clone())).await?;
+    Err(ProtocolError::Refused(refusal))
+}
@@ -0,0 +1,187 @@
+use crate::config::ConfigError;
+use rustls::{
+    RootCertStore,
+    crypto::{CryptoProvider, ring},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    server::WebPkiClientVerifier,
+};
+use serde::Deserialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// Server-side TLS settings, the `[tls]` section of a [`crate::config::ServerConfig`].
+///
+/// ```toml
+/// [tls]
+/// cert = "/etc/task_scheduler/server.pem"
+/// key = "/etc/task_scheduler/server.key"
+/// # Require client certificates signed by this CA (mutual TLS).
+/// client_ca = "/etc/task_scheduler/clients-ca.pem"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file holding the server certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM file holding the private key of the leaf certificate.
+    pub key: PathBuf,
+    /// PEM file of the CAs allowed to sign client certificates.
+    ///
+    /// When set, clients without a valid certificate are refused.
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Loads the certificates and key and builds the acceptor wrapping
+    /// accepted TCP connections.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::Io`] if a file cannot be read and
+    /// [`ConfigError::Tls`] if its content is unusable.
+    pub fn acceptor(&self) -> Result<TlsAcceptor, ConfigError> {
+        let builder = rustls::ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca {
+            Some(ca) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(load_roots(ca)?),
+                    provider(),
+                )
+                .build()
+                .map_err(|e| ConfigError::Tls(e.to_string()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder.with_single_cert(load_certs(&self.cert)?, load_key(&self.key)?)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Client-side TLS settings, ready to be handed to
+/// [`crate::client::ClientBuilder::tls`].
+///
+/// # Examples
+/// ```no_run
+/// # fn demo() -> Result<(), task_scheduler::config::ConfigError> {
+/// use task_scheduler::{client::Client, tls::ClientTls};
+///
+/// let tls = ClientTls::new("ca.pem")?.with_identity("client.pem", "client.key")?;
+/// let client = Client::builder("orchestrator.example.com:8443").tls(tls).build();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ClientTls {
+    roots: Arc<RootCertStore>,
+    config: Arc<rustls::ClientConfig>,
+    server_name: Option<ServerName<'static>>,
+}
+
+impl fmt::Debug for ClientTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientTls")
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClientTls {
+    /// Trusts the CA certificates found in the PEM file `ca`.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError`] if the file cannot be read or holds no
+    /// usable certificate.
+    pub fn new(ca: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let roots = Arc::new(load_roots(ca.as_ref())?);
+        let config = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(Arc::clone(&roots))
+            .with_no_client_auth();
+
+        Ok(Self {
+            roots,
+            config: Arc::new(config),
+            server_name: None,
+        })
+    }
+
+    /// Presents the certificate chain `cert` and its key `key` to servers
+    /// requiring mutual TLS.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError`] if the files cannot be read or do not match.
+    pub fn with_identity(
+        mut self,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> Result<Self, ConfigError> {
+        let config = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(Arc::clone(&self.roots))
+            .with_client_auth_cert(load_certs(cert.as_ref())?, load_key(key.as_ref())?)?;
+        self.config = Arc::new(config);
+        Ok(self)
+    }
+
+    /// Overrides the name checked against the server certificate.
+    ///
+    /// By default the host part of the address given to
+    /// [`crate::client::Client::builder`] is used.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::Tls`] if `name` is not a valid DNS name or IP address.
+    pub fn with_server_name(mut self, name: impl Into<String>) -> Result<Self, ConfigError> {
+        let name = ServerName::try_from(name.into()).map_err(|e| ConfigError::Tls(e.to_string()))?;
+        self.server_name = Some(name);
+        Ok(self)
+    }
+
+    /// Returns the connector and the name to verify for a server at `addr`.
+    pub(crate) fn connector(
+        &self,
+        addr: &str,
+    ) -> Result<(TlsConnector, ServerName<'static>), ConfigError> {
+        let name = match &self.server_name {
+            Some(name) => name.clone(),
+            None => {
+                let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                ServerName::try_from(host.to_owned()).map_err(|e| ConfigError::Tls(e.to_string()))?
+            }
+        };
+        Ok((TlsConnector::from(Arc::clone(&self.config)), name))
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, ConfigError> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ConfigError::Tls(format!("{}: {}", path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(ConfigError::Tls(format!("{}: no certificate found", path.display())));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, ConfigError> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| ConfigError::Tls(format!("{}: {}", path.display(), e)))
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, ConfigError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
@@ -0,0 +1,121 @@
+use serde::Deserialize;
+use std::{
+    fs, io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use tokio::net::{UnixListener, unix::UCred};
+
+/// Local listener settings, the `[unix]` section of a [`crate::config::ServerConfig`].
+///
+/// Clients on the same host connect through the socket file instead of TCP.
+/// Access is restricted twice: by the permissions of the socket file, and by
+/// the credentials the kernel reports for every connecting process
+/// (`SO_PEERCRED`).
+///
+/// ```toml
+/// tcp = false  # serve local clients only
+///
+/// [unix]
+/// path = "/run/task_scheduler.sock"
+/// mode = 0o660
+/// allowed_uids = [1000]
+/// allowed_gids = [100]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnixConfig {
+    /// Location of the socket file.
+    pub path: PathBuf,
+    /// Permission bits applied to the socket file, `0o600` by default.
+    #[serde(default = "default_mode")]
+    pub mode: u32,
+    /// User ids allowed to connect.
+    #[serde(default)]
+    pub allowed_uids: Vec<u32>,
+    /// Group ids allowed to connect.
+    ///
+    /// When both lists are empty any process able to open the socket file is
+    /// served; otherwise a peer must match at least one of them.
+    #[serde(default)]
+    pub allowed_gids: Vec<u32>,
+}
+
+fn default_mode() -> u32 {
+    0o600
+}
+
+impl UnixConfig {
+    /// Creates settings for a socket at `path` with the default permissions
+    /// and no credential check.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            mode: default_mode(),
+            allowed_uids: Vec::new(),
+            allowed_gids: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if a peer with these credentials may be served.
+    pub fn allows(&self, peer: &UCred) -> bool {
+        if self.allowed_uids.is_empty() && self.allowed_gids.is_empty() {
+            return true;
+        }
+        self.allowed_uids.contains(&peer.uid()) || self.allowed_gids.contains(&peer.gid())
+    }
+
+    /// Binds the socket file and applies [`UnixConfig::mode`].
+    ///
+    /// The socket is bound inside a private directory next to
+    /// [`UnixConfig::path`], given its permissions there and only then moved
+    /// into place, so that it is never reachable with broader permissions.
+    ///
+    /// A socket file left behind by a previous run is replaced, but one that
+    /// still accepts connections is reported as [`io::ErrorKind::AddrInUse`].
+    /// Any other file at that path is left alone and reported as
+    /// [`io::ErrorKind::AlreadyExists`].
+    pub(crate) fn bind(&self) -> io::Result<UnixListener> {
+        if let Ok(meta) = fs::symlink_metadata(&self.path) {
+            if !meta.file_type().is_socket() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", self.path.display()),
+                ));
+            }
+            if std::os::unix::net::UnixStream::connect(&self.path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is served by another process", self.path.display()),
+                ));
+            }
+            fs::remove_file(&self.path)?;
+        }
+
+        let name = self.path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} does not name a file", self.path.display()),
+            )
+        })?;
+        let staging = self
+            .path
+            .with_file_name(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+        // Left behind by a run that crashed with the same process id.
+        let _ = fs::remove_dir_all(&staging);
+        fs::DirBuilder::new().mode(0o700).create(&staging)?;
+        let result = self.bind_in(&staging);
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+
+    /// Binds the socket in the private directory `staging` and moves it to
+    /// [`UnixConfig::path`].
+    fn bind_in(&self, staging: &Path) -> io::Result<UnixListener> {
+        let bound = staging.join("socket");
+        let listener = UnixListener::bind(&bound)?;
+        fs::set_permissions(&bound, fs::Permissions::from_mode(self.mode))?;
+        fs::rename(&bound, &self.path)?;
+        Ok(listener)
+    }
+}
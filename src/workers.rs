@@ -1,18 +1,35 @@
 use crate::{
-    FilePath, HashAlgorithms, ServerMetrics, crypto::HashError, crypto::hash_reader,
-    protocol::{HashingPacket, ProtocolMessage},
+    HashAlgorithms, ServerMetrics,
+    config::ServerConfig,
+    constants::{MAX_XOF_OUTPUT_LEN, RESPONSE_TIMEOUT},
+    crypto::{HashError, hash_blake3, hash_reader, hash_xof_reader, open},
+    protocol::{
+        FailureReason, HashingPacket, Priority, ProtocolMessage, Progress, RequestId, TaskResponse,
+    },
+    queue::{Flow, WorkQueue},
+    quota::QuotaLease,
+    remote::RemoteFetcher,
+    sandbox::Sandbox,
 };
 use sha2::{Sha224, Sha256, Sha384, Sha512, Sha512_224, Sha512_256};
-use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512};
-use std::fs;
-use std::io::Read;
-use std::sync::{Arc, atomic::Ordering};
-use tokio::sync::{Mutex, mpsc, oneshot};
+use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512, Shake128, Shake256};
+use std::{
+    io::{self, Read},
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::mpsc,
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, debug, field, info, info_span, warn};
 
 /// High-level classification of tasks supported by the worker pool.
 ///
 /// This enum is used to categorize work before it is dispatched, allowing 
-/// for specialized handling or priority queuing of different task types.
+/// for specialized handling of different task types. The order in which
+/// queued tasks run is set by their [`Priority`] instead.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Task {
     /// A task dedicated to calculating file checksums.
@@ -21,46 +38,191 @@ pub enum Task {
     Other,
 }
 
-/// Orchestrates a thread pool for executing CPU-bound cryptographic tasks.
-///
-/// This module implements the "Fan-out" pattern. It consumes [`WorkItem`]s from a 
-/// shared queue and distributes them across a fixed number of asynchronous workers.
-/// Heavy hashing operations are offloaded to a blocking thread pool to prevent 
-/// starving the asynchronous runtime.
+// Orchestrates a thread pool for executing CPU-bound cryptographic tasks.
+//
+// This module implements the "Fan-out" pattern. It consumes `WorkItem`s from a
+// shared queue and distributes them across a fixed number of asynchronous workers.
+// Heavy hashing operations are offloaded to a blocking thread pool to prevent
+// starving the asynchronous runtime.
 
 /// A unit of work consisting of a task payload and a feedback channel.
 ///
-/// Each `WorkItem` contains a [`HashingPacket`] and a [`oneshot::Sender`] used to 
-/// communicate the result back to the original request handler.
+/// Each `WorkItem` contains a [`HashingPacket`], the [`RequestId`] chosen by the
+/// client and an [`mpsc::Sender`] feeding the outbound queue of the connection
+/// the request came from. Results are tagged with the id so they can be written
+/// back in whatever order the workers finish. Its [`CancellationToken`] lets the
+/// connection abandon the task, which is then answered with
+/// [`FailureReason::Cancelled`].
+///
+/// Every item carries a `task` tracing span, opened when the item is created
+/// and closed once its result is ready, that records the algorithm, the path,
+/// the priority, the total duration and the outcome of the task.
+#[derive(Debug)]
 pub struct WorkItem {
+    id: RequestId,
     packet: HashingPacket,
-    responder: oneshot::Sender<ProtocolMessage>,
+    responder: mpsc::Sender<ProtocolMessage>,
+    /// Where progress frames go instead of `responder`, if set.
+    progress: Option<mpsc::Sender<ProtocolMessage>>,
+    /// Closes the connection if the response cannot be handed to it.
+    hang_up: Option<CancellationToken>,
+    cancel: CancellationToken,
+    /// Counts the task against the quota of its client until it is dropped.
+    lease: Option<QuotaLease>,
+    /// The client the task is scheduled for.
+    flow: Option<Arc<Flow>>,
+    priority: Priority,
+    accepted: Instant,
+    span: Span,
 }
 
 impl WorkItem {
     /// Creates a new work envelope for the worker pool.
     ///
     /// # Arguments
+    /// * `id` - The client-chosen identifier echoed in the response.
     /// * `packet` - The data defining the task to be performed.
-    /// * `responder` - A [`oneshot::Sender`] used to transmit the result back 
+    /// * `responder` - An [`mpsc::Sender`] used to transmit the result back 
     ///   to the client's connection handler.
+    /// * `cancel` - Cancelled when the client no longer wants the result.
     #[inline]
     #[must_use]
-    pub fn new(packet: HashingPacket, responder: oneshot::Sender<ProtocolMessage>) -> Self {
-        Self { packet, responder }
+    pub fn new(
+        id: RequestId,
+        packet: HashingPacket,
+        responder: mpsc::Sender<ProtocolMessage>,
+        cancel: CancellationToken,
+    ) -> Self {
+        let span = info_span!(
+            "task",
+            id,
+            algorithm = %packet.algorithm(),
+            path = %packet.path(),
+            priority = Priority::Normal.name(),
+            duration_ms = field::Empty,
+            outcome = field::Empty,
+        );
+        Self {
+            id,
+            packet,
+            responder,
+            progress: None,
+            hang_up: None,
+            cancel,
+            lease: None,
+            flow: None,
+            priority: Priority::Normal,
+            accepted: Instant::now(),
+            span,
+        }
+    }
+
+    /// Counts the task against a client quota until it completes or is
+    /// turned away.
+    pub(crate) fn with_lease(mut self, lease: QuotaLease) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Sends the progress frames of the task through `progress`, so that
+    /// they never take the room of a response in the outbound queue.
+    pub(crate) fn with_progress(mut self, progress: mpsc::Sender<ProtocolMessage>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Cancels `hang_up` if the response of the task cannot be delivered,
+    /// to close the connection instead of leaving the client waiting.
+    pub(crate) fn with_hang_up(mut self, hang_up: CancellationToken) -> Self {
+        self.hang_up = Some(hang_up);
+        self
+    }
+
+    /// Schedules the task on behalf of the client of `flow`.
+    pub(crate) fn with_flow(mut self, flow: Arc<Flow>) -> Self {
+        self.flow = Some(flow);
+        self
+    }
+
+    /// Queues the task at `priority` instead of [`Priority::Normal`].
+    pub(crate) fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self.span.record("priority", priority.name());
+        self
+    }
+
+    /// Returns how urgently the task should run.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Returns the client the task is scheduled for, if any.
+    pub(crate) fn flow(&self) -> Option<&Flow> {
+        self.flow.as_deref()
+    }
+
+    /// Returns the client-chosen identifier of the task.
+    pub fn id(&self) -> RequestId {
+        self.id
     }
 
     /// Provides a read-only reference to the task's data packet.
     pub fn packet(&self) -> &HashingPacket {
         &self.packet
     }
+
+    /// Returns the token abandoning the task once cancelled.
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Returns the tracing span covering the lifetime of the task.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Answers the task with [`TaskResponse::Busy`] instead of running it.
+    pub(crate) async fn reject(self, retry_after: Duration, queue_depth: usize) {
+        self.span.record("outcome", "busy");
+        self.span.in_scope(|| debug!(queue_depth, "queue is full, task turned away"));
+        let response = TaskResponse::Busy {
+            id: self.id,
+            retry_after,
+            queue_depth: u32::try_from(queue_depth).unwrap_or(u32::MAX),
+        };
+        respond(&self.responder, response, self.hang_up.as_ref()).await;
+    }
+}
+
+/// Hands `response` to the writer of its connection.
+///
+/// The outbound queue has room for every response a connection may have in
+/// flight, so this should not wait. Should it still be full after
+/// [`RESPONSE_TIMEOUT`], the connection is closed through `hang_up` rather
+/// than holding the worker or leaving the client waiting for an answer
+/// that will not come.
+async fn respond(
+    responder: &mpsc::Sender<ProtocolMessage>,
+    response: TaskResponse,
+    hang_up: Option<&CancellationToken>,
+) {
+    let message = ProtocolMessage::TaskResponse(response);
+    if tokio::time::timeout(RESPONSE_TIMEOUT, responder.send(message)).await.is_err() {
+        warn!("client is not reading its responses, closing the connection");
+        if let Some(hang_up) = hang_up {
+            hang_up.cancel();
+        }
+    }
 }
 
 /// Initializes and starts a pool of worker tasks.
 ///
 /// # Arguments
-/// * `receiver` - An MPSC channel receiver used to listen for incoming tasks.
-/// * `num_workers` - The number of concurrent asynchronous tasks to spawn.
+/// * `queue` - The queue the workers take their tasks from, until it is
+///   closed and empty.
+/// * `config` - Provides the number of workers to spawn, the hashing buffer
+///   size and the [`crate::remote::RemoteConfig`] used for remote targets.
+/// * `sandbox` - The policy local targets are opened through.
 /// * `metrics` - Shared atomic counters for tracking system health and throughput.
 ///
 /// # Threading
@@ -68,77 +230,325 @@ impl WorkItem {
 /// task is received, it uses [`tokio::task::spawn_blocking`] to handle the 
 /// computationally expensive hashing, ensuring the orchestrator remains responsive.
 pub async fn start_worker_pool(
-    receiver: mpsc::Receiver<WorkItem>,
-    num_workers: usize,
+    queue: Arc<WorkQueue>,
+    config: &ServerConfig,
+    sandbox: Sandbox,
     metrics: Arc<ServerMetrics>,
 ) {
-    let receiver = Arc::new(Mutex::new(receiver));
+    let executor = Arc::new(Executor {
+        sandbox,
+        remote: RemoteFetcher::new(config.remote.clone()),
+        buffer_size: config.hash_buffer_size,
+        task_timeout: config.task_timeout,
+        max_input_size: config.max_input_size,
+        progress_interval: config.progress_interval,
+        metrics: Arc::clone(&metrics),
+    });
 
-    for _id in 0..num_workers {
-        let rx = Arc::clone(&receiver);
+    for worker in 0..config.workers {
+        let queue = Arc::clone(&queue);
         let metrics = Arc::clone(&metrics);
-        
+        let executor = Arc::clone(&executor);
+
         tokio::spawn(async move {
             loop {
-                let work = {
-                    let mut lock = rx.lock().await;
-                    lock.recv().await
-                };
+                let work = queue.pop().await;
 
                 if let Some(item) = work {
-                    let WorkItem { packet, responder } = item;
-                    let metrics_clone = Arc::clone(&metrics);
-
-                    let result = tokio::task::spawn_blocking(move || {
-                        metrics_clone.processed_tasks.fetch_add(1, Ordering::Relaxed);
-
-                        let algo = packet.algorithm();
-                        let path = packet.path();
-
-                        match algo {
-                            HashAlgorithms::SHA224 => hash_reader::<Sha224>(path),
-                            HashAlgorithms::SHA256 => hash_reader::<Sha256>(path),
-                            HashAlgorithms::SHA384 => hash_reader::<Sha384>(path),
-                            HashAlgorithms::SHA512 => hash_reader::<Sha512>(path),
-                            HashAlgorithms::SHA512_224 => hash_reader::<Sha512_224>(path),
-                            HashAlgorithms::SHA512_256 => hash_reader::<Sha512_256>(path),
-
-                            HashAlgorithms::SHA3_224 => hash_reader::<Sha3_224>(path),
-                            HashAlgorithms::SHA3_256 => hash_reader::<Sha3_256>(path),
-                            HashAlgorithms::SHA3_384 => hash_reader::<Sha3_384>(path),
-                            HashAlgorithms::SHA3_512 => hash_reader::<Sha3_512>(path),
-
-                            HashAlgorithms::BLAKE3 => {
-                                let mut src = match path {
-                                    FilePath::Local(p) => fs::File::open(p).map_err(HashError::Io)?,
-                                    FilePath::Remote(_) => return Err(HashError::NotImplemented),
-                                };
-
-                                let mut hasher = blake3::Hasher::new();
-                                let mut buffer = [0u8; 8192];
-                                loop {
-                                    let count = src.read(&mut buffer).map_err(HashError::Io)?;
-                                    if count == 0 { break; }
-                                    hasher.update(&buffer[..count]);
-                                }
-                                Ok(hasher.finalize().to_hex().to_string())
-                            }
-                            _ => Err(HashError::NotImplemented),
-                        }
-                    })
-                    .await;
+                    let WorkItem {
+                        id,
+                        packet,
+                        responder,
+                        progress,
+                        hang_up,
+                        cancel,
+                        lease: _lease,
+                        flow: _,
+                        priority: _,
+                        accepted,
+                        span,
+                    } = item;
+                    metrics.queued_tasks.fetch_sub(1, Ordering::Relaxed);
+                    // Nobody is left to read the result, e.g. the connection
+                    // was closed by a shutdown whose deadline expired.
+                    if responder.is_closed() {
+                        metrics.pending_tasks.fetch_sub(1, Ordering::Relaxed);
+                        span.in_scope(|| debug!("client is gone, task skipped"));
+                        continue;
+                    }
 
-                    let final_response = match result {
-                        Ok(Ok(h)) => ProtocolMessage::TaskResponse(crate::protocol::TaskResponse::Success(h)),
-                        Ok(Err(_)) => ProtocolMessage::TaskResponse(crate::protocol::TaskResponse::Failed),
-                        Err(_) => ProtocolMessage::TaskResponse(crate::protocol::TaskResponse::Failed),
-                    };
+                    let progress = packet
+                        .progress()
+                        .then(|| progress.unwrap_or_else(|| responder.clone()));
+                    let response = run(id, packet, Arc::clone(&executor), cancel, progress)
+                        .instrument(span.clone())
+                        .await;
 
-                    let _ = responder.send(final_response);
+                    let latency = accepted.elapsed();
+                    metrics.record_latency(latency);
+                    span.record("duration_ms", latency.as_millis() as u64);
+                    span.in_scope(|| match &response {
+                        TaskResponse::Success { .. } => {
+                            span.record("outcome", "success");
+                            info!(worker, "task completed");
+                        }
+                        TaskResponse::Failed { reason, message, .. } => {
+                            metrics.record_failure(*reason);
+                            span.record("outcome", reason.name());
+                            warn!(worker, error = message.as_deref(), "task failed");
+                        }
+                        TaskResponse::Busy { .. } => {
+                            span.record("outcome", "busy");
+                        }
+                        TaskResponse::QuotaExceeded { .. } => {
+                            span.record("outcome", "quota_exceeded");
+                        }
+                    });
+                    metrics.pending_tasks.fetch_sub(1, Ordering::Relaxed);
+
+                    respond(&responder, response, hang_up.as_ref()).await;
                 } else {
                     break;
                 }
             }
         });
     }
-}
\ No newline at end of file
+}
+
+/// Hashes `packet` on the blocking pool and turns the outcome into a response.
+///
+/// The task is answered with [`FailureReason::Cancelled`] as soon as `cancel`
+/// fires, and with [`FailureReason::Timeout`] once
+/// [`ServerConfig::task_timeout`] expires. Either way the blocking job is
+/// told to stop and does so at its next read of the input; a read that never
+/// returns (e.g. a FIFO without a writer) keeps its thread until it does.
+///
+/// When `progress` is set, the hashing loop reports its advance through it.
+async fn run(
+    id: RequestId,
+    packet: HashingPacket,
+    executor: Arc<Executor>,
+    cancel: CancellationToken,
+    progress: Option<mpsc::Sender<ProtocolMessage>>,
+) -> TaskResponse {
+    let failed = |reason, message: String| TaskResponse::Failed {
+        id,
+        reason,
+        message: Some(message),
+    };
+    if cancel.is_cancelled() {
+        return failed(FailureReason::Cancelled, String::from("Task was cancelled before it started"));
+    }
+
+    debug!("task started");
+    let span = Span::current();
+    let job_cancel = cancel.clone();
+    let task_timeout = executor.task_timeout;
+    let job = tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        executor.metrics.record_task(*packet.algorithm());
+        let reporter = progress.map(|responder| Reporter::new(id, responder, executor.progress_interval));
+        executor.execute(&packet, &job_cancel, reporter)
+    });
+    let deadline = async {
+        match task_timeout {
+            Some(duration) => sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let result = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => {
+            return failed(FailureReason::Cancelled, String::from("Task was cancelled"));
+        }
+        result = job => result,
+        _ = deadline => {
+            cancel.cancel();
+            return failed(
+                FailureReason::Timeout,
+                format!("Task exceeded its deadline of {:?}", task_timeout.unwrap_or_default()),
+            );
+        }
+    };
+
+    match result {
+        Ok(Ok(digest)) => TaskResponse::Success { id, digest },
+        Ok(Err(e)) => failed(e.reason(), e.to_string()),
+        Err(e) => failed(FailureReason::WorkerPanic, e.to_string()),
+    }
+}
+
+/// Everything a worker needs to run a task, shared by the whole pool.
+struct Executor {
+    sandbox: Sandbox,
+    remote: RemoteFetcher,
+    buffer_size: usize,
+    task_timeout: Option<Duration>,
+    max_input_size: Option<u64>,
+    progress_interval: Duration,
+    metrics: Arc<ServerMetrics>,
+}
+
+impl Executor {
+    /// Runs a hashing task to completion on the current (blocking) thread.
+    ///
+    /// The request is validated before its source is opened, so that an
+    /// unsupported algorithm never triggers a download. Every read of the
+    /// input checks `cancel` and [`ServerConfig::max_input_size`], and gives
+    /// `reporter` a chance to send a progress frame.
+    fn execute(
+        &self,
+        packet: &HashingPacket,
+        cancel: &CancellationToken,
+        reporter: Option<Reporter>,
+    ) -> Result<String, HashError> {
+        let algo = *packet.algorithm();
+
+        if !algo.is_implemented() {
+            return Err(HashError::UnsupportedAlgorithm(algo));
+        }
+        if packet.output_len().is_some() && !algo.is_xof() {
+            return Err(HashError::InvalidOutputLength(packet.output_len()));
+        }
+
+        let source = open(packet.path(), &self.sandbox, &self.remote)?;
+        let mut src = Metered {
+            inner: source.reader,
+            metrics: &self.metrics,
+            cancel,
+            remaining: self.max_input_size,
+            read: 0,
+            reporter: reporter.map(|reporter| reporter.sized(source.len)),
+        };
+        let src = &mut src;
+        let buf = self.buffer_size;
+
+        match algo {
+            HashAlgorithms::SHA224 => hash_reader::<Sha224>(src, buf),
+            HashAlgorithms::SHA256 => hash_reader::<Sha256>(src, buf),
+            HashAlgorithms::SHA384 => hash_reader::<Sha384>(src, buf),
+            HashAlgorithms::SHA512 => hash_reader::<Sha512>(src, buf),
+            HashAlgorithms::SHA512_224 => hash_reader::<Sha512_224>(src, buf),
+            HashAlgorithms::SHA512_256 => hash_reader::<Sha512_256>(src, buf),
+
+            HashAlgorithms::SHA3_224 => hash_reader::<Sha3_224>(src, buf),
+            HashAlgorithms::SHA3_256 => hash_reader::<Sha3_256>(src, buf),
+            HashAlgorithms::SHA3_384 => hash_reader::<Sha3_384>(src, buf),
+            HashAlgorithms::SHA3_512 => hash_reader::<Sha3_512>(src, buf),
+            HashAlgorithms::SHAKE128 => {
+                hash_xof_reader::<Shake128>(src, xof_output_len(packet)?, buf)
+            }
+            HashAlgorithms::SHAKE256 => {
+                hash_xof_reader::<Shake256>(src, xof_output_len(packet)?, buf)
+            }
+
+            HashAlgorithms::BLAKE3 => hash_blake3(src, buf),
+            other => Err(HashError::UnsupportedAlgorithm(other)),
+        }
+    }
+}
+
+/// A reader adding every byte it yields to [`ServerMetrics::bytes_hashed`].
+///
+/// It fails with [`io::ErrorKind::TimedOut`] once its task was cancelled and
+/// with [`io::ErrorKind::FileTooLarge`] once more than the allowed number of
+/// bytes was read, which the hashing loop reports as a task failure.
+struct Metered<'a, R> {
+    inner: R,
+    metrics: &'a ServerMetrics,
+    cancel: &'a CancellationToken,
+    remaining: Option<u64>,
+    read: u64,
+    reporter: Option<Reporter>,
+}
+
+impl<R: Read> Read for Metered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "task cancelled"));
+        }
+
+        let count = self.inner.read(buf)?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.checked_sub(count as u64).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::FileTooLarge, "input exceeds the maximum size")
+            })?;
+        }
+        self.metrics
+            .bytes_hashed
+            .fetch_add(count as u64, Ordering::Relaxed);
+        self.read += count as u64;
+        if let Some(reporter) = &mut self.reporter
+            && !self.cancel.is_cancelled()
+        {
+            reporter.tick(self.read);
+        }
+        Ok(count)
+    }
+}
+
+/// Sends the [`Progress`] frames of a task, at most once per interval.
+struct Reporter {
+    id: RequestId,
+    responder: mpsc::Sender<ProtocolMessage>,
+    total_bytes: Option<u64>,
+    interval: Duration,
+    started: Instant,
+    next: Instant,
+}
+
+impl Reporter {
+    fn new(id: RequestId, responder: mpsc::Sender<ProtocolMessage>, interval: Duration) -> Self {
+        let started = Instant::now();
+        Self {
+            id,
+            responder,
+            total_bytes: None,
+            interval,
+            started,
+            next: started + interval,
+        }
+    }
+
+    /// Records the size of the input, once it was opened.
+    fn sized(self, total_bytes: Option<u64>) -> Self {
+        Self { total_bytes, ..self }
+    }
+
+    /// Reports `bytes_processed` if the interval elapsed since the last frame.
+    ///
+    /// A frame that does not fit in the outbound queue of the connection is
+    /// dropped: the hashing loop never waits for the client.
+    fn tick(&mut self, bytes_processed: u64) {
+        let now = Instant::now();
+        if now < self.next {
+            return;
+        }
+        self.next = now + self.interval;
+
+        let elapsed = now.duration_since(self.started).as_secs_f64();
+        let progress = Progress {
+            id: self.id,
+            bytes_processed,
+            total_bytes: self.total_bytes,
+            bytes_per_second: (bytes_processed as f64 / elapsed) as u64,
+        };
+        let _ = self.responder.try_send(ProtocolMessage::Progress(progress));
+    }
+}
+
+/// Resolves the number of output bytes to squeeze for an XOF request.
+///
+/// Falls back to [`HashAlgorithms::default_output_len`] when the client did
+/// not choose a length, and rejects lengths outside `1..=MAX_XOF_OUTPUT_LEN`.
+fn xof_output_len(packet: &HashingPacket) -> Result<usize, HashError> {
+    let requested = packet
+        .output_len()
+        .or_else(|| packet.algorithm().default_output_len());
+
+    match requested {
+        Some(len) if (1..=MAX_XOF_OUTPUT_LEN).contains(&len) => Ok(len as usize),
+        other => Err(HashError::InvalidOutputLength(other)),
+    }
+}
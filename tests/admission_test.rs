@@ -0,0 +1,170 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    client::{Client, ClientError},
+    config::ServerConfig,
+    protocol::{Priority, ProtocolMessage, TaskResponse, read_protocol},
+    queue::{AdmissionConfig, AdmissionPolicy},
+};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+const RETRY_AFTER: Duration = Duration::from_millis(500);
+
+/// A server with a single worker and room for a single queued task.
+fn serve(policy: AdmissionPolicy) -> std::net::SocketAddr {
+    let config = ServerConfig {
+        workers: 1,
+        queue_depth: 1,
+        admission: AdmissionConfig {
+            policy,
+            retry_after: RETRY_AFTER,
+        },
+        ..common::fifo_config()
+    };
+    common::serve(config)
+}
+
+fn release(fifo: PathBuf) {
+    tokio::task::spawn_blocking(move || std::fs::write(fifo, b"done").unwrap());
+}
+
+async fn send(stream: &mut TcpStream, id: u64, path: &str) {
+    let frame = common::hash_request(id, HashAlgorithms::SHA256, path);
+    stream.write_all(&frame).await.unwrap();
+}
+
+async fn response(stream: &mut TcpStream) -> TaskResponse {
+    match read_protocol(stream).await.unwrap() {
+        ProtocolMessage::TaskResponse(response) => response,
+        other => panic!("Expected a task response, got {:?}", other),
+    }
+}
+
+/// Occupies the only worker with a FIFO and fills the queue with another.
+async fn saturate(stream: &mut TcpStream, running: &Path, queued: &Path) {
+    send(stream, 1, running.to_str().unwrap()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    send(stream, 2, queued.to_str().unwrap()).await;
+}
+
+fn assert_busy(response: TaskResponse, expected: u64) {
+    match response {
+        TaskResponse::Busy {
+            id,
+            retry_after,
+            queue_depth,
+        } => {
+            assert_eq!(id, expected);
+            assert_eq!(retry_after, RETRY_AFTER);
+            assert_eq!(queue_depth, 1);
+        }
+        other => panic!("Expected a busy response, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn full_queues_reject_new_tasks() {
+    let mut stream = common::connect_to(serve(AdmissionPolicy::Reject)).await;
+    let (running, queued) = (common::fifo("reject-running"), common::fifo("reject-queued"));
+    saturate(&mut stream, &running, &queued).await;
+
+    send(&mut stream, 3, "Cargo.toml").await;
+    assert_busy(response(&mut stream).await, 3);
+
+    release(running);
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 1, .. }));
+    release(queued);
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 2, .. }));
+}
+
+#[tokio::test]
+async fn clients_are_told_to_back_off() {
+    let addr = serve(AdmissionPolicy::Reject);
+    let mut stream = common::connect_to(addr).await;
+    let (running, queued) = (common::fifo("client-running"), common::fifo("client-queued"));
+    saturate(&mut stream, &running, &queued).await;
+
+    let client = Client::builder(addr.to_string()).build();
+    let error = client
+        .hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256)
+        .await
+        .unwrap_err();
+    assert!(error.is_retryable());
+    assert!(matches!(
+        error,
+        ClientError::Busy { retry_after: RETRY_AFTER, queue_depth: 1 }
+    ));
+
+    release(running);
+    release(queued);
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 1, .. }));
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 2, .. }));
+}
+
+#[tokio::test]
+async fn full_queues_shed_their_oldest_task() {
+    let mut stream = common::connect_to(serve(AdmissionPolicy::ShedOldest)).await;
+    let (running, queued) = (common::fifo("shed-running"), common::fifo("shed-queued"));
+    saturate(&mut stream, &running, &queued).await;
+
+    send(&mut stream, 3, "Cargo.toml").await;
+    assert_busy(response(&mut stream).await, 2);
+
+    release(running);
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 1, .. }));
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 3, .. }));
+}
+
+#[tokio::test]
+async fn more_urgent_tasks_are_not_shed() {
+    let mut stream = common::connect_to(serve(AdmissionPolicy::ShedOldest)).await;
+    let running = common::fifo("shed-urgent-running");
+    send(&mut stream, 1, running.to_str().unwrap()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    for (id, priority) in [(2, Priority::Interactive), (3, Priority::Bulk)] {
+        let frame = common::prioritized_request(id, HashAlgorithms::SHA256, "Cargo.toml", priority);
+        stream.write_all(&frame).await.unwrap();
+    }
+    assert_busy(response(&mut stream).await, 3);
+
+    release(running);
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 1, .. }));
+    assert!(matches!(response(&mut stream).await, TaskResponse::Success { id: 2, .. }));
+}
+
+#[tokio::test]
+async fn full_queues_block_by_default() {
+    let mut stream = common::connect_to(serve(AdmissionPolicy::Block)).await;
+    let (running, queued) = (common::fifo("block-running"), common::fifo("block-queued"));
+    saturate(&mut stream, &running, &queued).await;
+
+    send(&mut stream, 3, "Cargo.toml").await;
+    let waited = tokio::time::timeout(Duration::from_millis(300), read_protocol(&mut stream)).await;
+    assert!(waited.is_err(), "the blocked task was answered");
+
+    release(running);
+    release(queued);
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        match response(&mut stream).await {
+            TaskResponse::Success { id, .. } => ids.push(id),
+            other => panic!("Expected a success, got {:?}", other),
+        }
+    }
+    ids.sort();
+    assert_eq!(ids, [1, 2, 3]);
+}
+
+#[test]
+fn admission_settings_are_parsed() {
+    let config =
+        ServerConfig::from_toml_str("[admission]\npolicy = \"shed_oldest\"\nretry_after = \"2s\"\n").unwrap();
+    assert_eq!(config.admission.policy, AdmissionPolicy::ShedOldest);
+    assert_eq!(config.admission.retry_after, Duration::from_secs(2));
+    assert_eq!(ServerConfig::default().admission.policy, AdmissionPolicy::Block);
+    assert!(ServerConfig::from_toml_str("[admission]\npolicy = \"drop\"\n").is_err());
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    auth::{AuthConfig, Credentials},
+    client::{Client, ClientError},
+    config::{ConfigError, ServerConfig},
+    protocol::{
+        Hello, ProtocolError, ProtocolMessage, ServerError, client_handshake_with,
+        read_protocol, write_protocol,
+    },
+};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+const KEY: &str = "correct horse battery staple";
+
+fn serve_with_auth() -> std::net::SocketAddr {
+    let auth = AuthConfig::default().with_key("ci", KEY);
+    let config = ServerConfig::builder().workers(2).auth(auth).build().unwrap();
+    common::serve(config)
+}
+
+async fn hash(addr: std::net::SocketAddr, credentials: Option<Credentials>) -> Result<String, ClientError> {
+    let mut builder = Client::builder(addr.to_string());
+    if let Some(credentials) = credentials {
+        builder = builder.credentials(credentials);
+    }
+    builder
+        .build()
+        .hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256)
+        .await
+}
+
+#[tokio::test]
+async fn clients_holding_a_key_are_served() {
+    let addr = serve_with_auth();
+    let digest = hash(addr, Some(Credentials::new("ci", KEY))).await.unwrap();
+    assert_eq!(digest.len(), 64);
+}
+
+#[tokio::test]
+async fn wrong_keys_and_unknown_identities_are_refused() {
+    let addr = serve_with_auth();
+    for credentials in [
+        Credentials::new("ci", "not the right key at all"),
+        Credentials::new("intruder", KEY),
+    ] {
+        match hash(addr, Some(credentials)).await {
+            Err(ClientError::Protocol(ProtocolError::Refused(ServerError::AuthenticationFailed))) => {}
+            other => panic!("Expected an authentication failure, got {:?}", other),
+        }
+    }
+
+    match hash(addr, None).await {
+        Err(ClientError::Protocol(ProtocolError::MissingCredentials)) => {}
+        other => panic!("Expected missing credentials, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn requests_are_not_dispatched_before_authentication() {
+    let mut stream = TcpStream::connect(serve_with_auth()).await.unwrap();
+    write_protocol(&mut stream, &ProtocolMessage::Hello(Hello::new())).await.unwrap();
+    assert!(matches!(
+        read_protocol(&mut stream).await.unwrap(),
+        ProtocolMessage::Challenge(_)
+    ));
+
+    let request = common::hash_request(1, HashAlgorithms::SHA256, "Cargo.toml");
+    stream.write_all(&request).await.unwrap();
+    match read_protocol(&mut stream).await.unwrap() {
+        ProtocolMessage::Error(ServerError::AuthenticationFailed) => {}
+        other => panic!("Expected an authentication failure, got {:?}", other),
+    }
+    assert!(read_protocol(&mut stream).await.is_err());
+}
+
+#[tokio::test]
+async fn answers_cannot_be_replayed() {
+    let addr = serve_with_auth();
+    let credentials = Credentials::new("ci", KEY);
+
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    write_protocol(&mut first, &ProtocolMessage::Hello(Hello::new())).await.unwrap();
+    let ProtocolMessage::Challenge(challenge) = read_protocol(&mut first).await.unwrap() else {
+        panic!("Expected a challenge");
+    };
+    let answer = credentials.respond(&challenge);
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    write_protocol(&mut second, &ProtocolMessage::Hello(Hello::new())).await.unwrap();
+    assert!(matches!(
+        read_protocol(&mut second).await.unwrap(),
+        ProtocolMessage::Challenge(_)
+    ));
+    write_protocol(&mut second, &ProtocolMessage::Authenticate(answer)).await.unwrap();
+    assert!(matches!(
+        read_protocol(&mut second).await.unwrap(),
+        ProtocolMessage::Error(ServerError::AuthenticationFailed)
+    ));
+
+    // Credentials given to a server without authentication are simply unused.
+    let mut stream = TcpStream::connect(common::server_addr()).await.unwrap();
+    assert!(client_handshake_with(&mut stream, Some(&credentials)).await.is_ok());
+}
+
+#[test]
+fn auth_settings_are_validated() {
+    let config = ServerConfig::from_toml_str(&format!("[auth.keys]\nci = \"{}\"\n", KEY)).unwrap();
+    assert!(config.auth.unwrap().keys.contains_key("ci"));
+
+    assert!(matches!(
+        ServerConfig::from_toml_str("[auth.keys]\nci = \"short\"\n"),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        ServerConfig::builder().auth(AuthConfig::default()).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+}
@@ -0,0 +1,115 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    client::{Client, ClientError},
+    config::ServerConfig,
+    protocol::{FailureReason, ProtocolMessage, TaskResponse, read_protocol, write_protocol},
+    sandbox::SandboxConfig,
+};
+use tokio::io::AsyncWriteExt;
+
+/// A server hashing special files, with no task deadline so that
+/// `/dev/zero` is hashed until cancelled, and its metrics endpoint port.
+fn serve() -> (SocketAddr, u16) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let sandbox = SandboxConfig {
+        allow_special_files: true,
+        ..SandboxConfig::default()
+    };
+    let config = ServerConfig::builder()
+        .workers(1)
+        .sandbox(sandbox)
+        .task_timeout(None)
+        .metrics_bind(format!("127.0.0.1:{}", port))
+        .build()
+        .unwrap();
+    (common::serve(config), port)
+}
+
+/// Waits until a cancelled task was counted and its blocking loop stopped
+/// feeding bytes to the hasher.
+async fn assert_hashing_stops(port: u16) {
+    let cancelled = "task_scheduler_task_failures_total{reason=\"cancelled\"}";
+    let bytes = "task_scheduler_bytes_hashed_total";
+    let mut previous = None;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let current = common::metric(port, bytes).await;
+        if common::metric(port, cancelled).await == Some(1) && current.is_some() && current == previous {
+            return;
+        }
+        previous = current;
+    }
+    panic!("the cancelled task kept hashing");
+}
+
+#[tokio::test]
+async fn tasks_are_cancelled_on_request() {
+    let (addr, port) = serve();
+    let mut stream = common::connect_to(addr).await;
+    let frame = common::hash_request(7, HashAlgorithms::SHA256, "/dev/zero");
+    stream.write_all(&frame).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Unknown ids are ignored.
+    write_protocol(&mut stream, &ProtocolMessage::CancelTask(8)).await.unwrap();
+    write_protocol(&mut stream, &ProtocolMessage::CancelTask(7)).await.unwrap();
+    match read_protocol(&mut stream).await.unwrap() {
+        ProtocolMessage::TaskResponse(TaskResponse::Failed {
+            id: 7,
+            reason: FailureReason::Cancelled,
+            ..
+        }) => {}
+        other => panic!("Expected a cancelled task, got {:?}", other),
+    }
+    assert_hashing_stops(port).await;
+
+    // The connection and the only worker keep serving.
+    let frame = common::hash_request(8, HashAlgorithms::SHA256, "Cargo.toml");
+    assert!(matches!(
+        common::roundtrip(&mut stream, &frame).await,
+        TaskResponse::Success { id: 8, .. }
+    ));
+}
+
+#[tokio::test]
+async fn tasks_are_cancelled_when_the_client_disconnects() {
+    let (addr, port) = serve();
+    let mut stream = common::connect_to(addr).await;
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, "/dev/zero");
+    stream.write_all(&frame).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(stream);
+
+    assert_hashing_stops(port).await;
+}
+
+#[tokio::test]
+async fn timed_out_calls_cancel_their_task() {
+    let (addr, port) = serve();
+    let client = Client::builder(addr.to_string())
+        .request_timeout(Duration::from_millis(200))
+        .build();
+    match client
+        .hash(FilePath::Local(String::from("/dev/zero")), HashAlgorithms::SHA256)
+        .await
+    {
+        Err(ClientError::Timeout) => {}
+        other => panic!("Expected a timeout, got {:?}", other),
+    }
+
+    assert_hashing_stops(port).await;
+    assert!(client
+        .hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256)
+        .await
+        .is_ok());
+}
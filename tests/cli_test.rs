@@ -1,46 +1,82 @@
+mod common;
+
+use sha3::{
+    Shake128,
+    digest::{ExtendableOutput, Update},
+};
 use task_scheduler::{
     FilePath, HashAlgorithms,
-    protocol::{HashingPacket, TaskRequest, read_protocol, ProtocolMessage},
+    protocol::{FailureReason, HashingPacket, Priority, TaskRequest, TaskResponse, read_protocol, ProtocolMessage},
 };
 use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
+
+const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
 
 #[tokio::test]
 async fn test_client_example() {
     
-    let task = ProtocolMessage::TaskRequest(TaskRequest::HashPacket(HashingPacket {
-        algorithm: HashAlgorithms::SHAKE128,
-        path: FilePath::Local(String::from(
-            "/home/bluetox/Developpement/rust/task_scheduler/Cargo.toml",
-        )),
-    }));
-    let mut stream = TcpStream::connect("127.0.0.1:8080").await.unwrap();
-    stream.write(&task.into_packet().unwrap()).await.unwrap();
+    let task = ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 1,
+        packet: HashingPacket {
+            algorithm: HashAlgorithms::SHAKE128,
+            path: FilePath::Local(String::from(MANIFEST)),
+            output_len: Some(48),
+            progress: false,
+        },
+        priority: Priority::Normal,
+    });
+    let mut stream = common::connect().await;
+    stream.write_all(&task.into_packet().unwrap()).await.unwrap();
     let packet = read_protocol(&mut stream).await.unwrap();
-    println!("Packet: {:?}", packet);
+
+    let mut hasher = Shake128::default();
+    hasher.update(&std::fs::read(MANIFEST).unwrap());
+    let expected: String = hasher
+        .finalize_boxed(48)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    match packet {
+        ProtocolMessage::TaskResponse(TaskResponse::Success { id: 1, digest }) => {
+            assert_eq!(digest.len(), 96);
+            assert_eq!(digest, expected);
+        }
+        other => panic!("Expected a digest, got {:?}", other),
+    }
 }
 
 #[tokio::test]
 async fn fake_path() {
     
-    let task = ProtocolMessage::TaskRequest(TaskRequest::HashPacket(HashingPacket {
-        algorithm: HashAlgorithms::SHA224,
-        path: FilePath::Local(String::from(
-            "/dev/zero",
-        )),
-    }));
-    let mut stream = TcpStream::connect("127.0.0.1:8080").await.unwrap();
-    stream.write(&task.into_packet().unwrap()).await.unwrap();
+    let task = ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 1,
+        packet: HashingPacket {
+            algorithm: HashAlgorithms::SHA224,
+            path: FilePath::Local(String::from(
+                "/dev/zero",
+            )),
+            output_len: None,
+            progress: false,
+        },
+        priority: Priority::Normal,
+    });
+    let mut stream = common::connect().await;
+    stream.write_all(&task.into_packet().unwrap()).await.unwrap();
     let packet = read_protocol(&mut stream).await.unwrap();
-    println!("Packet: {:?}", packet);
+    assert!(matches!(
+        packet,
+        ProtocolMessage::TaskResponse(TaskResponse::Failed {
+            reason: FailureReason::PathNotAllowed,
+            ..
+        })
+    ));
 }
 
 use rand::{RngCore, rng};
 
 #[tokio::test]
 async fn test_random_junk_bytes() {
-    let mut stream = TcpStream::connect("127.0.0.1:8080").await
-        .expect("Server must be running for this test");
+    let mut stream = common::connect().await;
 
     let mut junk_payload = vec![0u8; 100]; 
     rng().fill_bytes(&mut junk_payload);
@@ -60,4 +96,4 @@ async fn test_random_junk_bytes() {
             println!("Test Passed: Server correctly rejected junk. Error: {:?}", e);
         }
     }
-}
\ No newline at end of file
+}
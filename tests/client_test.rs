@@ -0,0 +1,140 @@
+mod common;
+
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    client::{Client, ClientError},
+    config::ServerConfig,
+    constants::{MAX_PACKET_SIZE, PROTOCOL_VERSION},
+    protocol::{
+        FailureReason, HashingPacket, ProtocolMessage, TaskRequest, TaskResponse, Welcome,
+        read_protocol,
+    },
+};
+use tokio::io::AsyncWriteExt;
+
+fn local(path: &str) -> FilePath {
+    FilePath::Local(String::from(path))
+}
+
+#[tokio::test]
+async fn client_hashes_single_and_batched_requests() {
+    let client = Client::builder(common::server_addr().to_string())
+        .pool_size(2)
+        .build();
+    let expected = format!("{:x}", Sha256::digest(std::fs::read("Cargo.toml").unwrap()));
+
+    let digest = client.hash(local("Cargo.toml"), HashAlgorithms::SHA256).await.unwrap();
+    assert_eq!(digest, expected);
+
+    let mut packets = Vec::new();
+    for i in 0..50 {
+        let path = if i % 10 == 0 { "/does/not/exist" } else { "Cargo.toml" };
+        packets.push(HashingPacket {
+            algorithm: HashAlgorithms::SHA256,
+            path: local(path),
+            output_len: None,
+            progress: false,
+        });
+    }
+    let results = client.hash_batch(packets).await;
+    assert_eq!(results.len(), 50);
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(digest) => assert_eq!(digest, expected),
+            Err(ClientError::Failed { reason, .. }) => {
+                assert_eq!(i % 10, 0);
+                assert_eq!(reason, FailureReason::NotFound);
+            }
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+}
+
+#[tokio::test]
+async fn client_rejects_algorithms_the_server_lacks() {
+    let client = Client::builder(common::server_addr().to_string()).build();
+    let result = client.hash(local("Cargo.toml"), HashAlgorithms::UNIMPLEMENTED).await;
+    assert!(matches!(result, Err(ClientError::UnsupportedAlgorithm(_))));
+}
+
+#[tokio::test]
+async fn client_calls_honour_their_deadline() {
+    let fifo = common::fifo("deadline.fifo");
+
+    let client = Client::builder(common::fifo_server_addr().to_string()).build();
+    let packet = HashingPacket {
+        algorithm: HashAlgorithms::SHA256,
+        path: local(fifo.to_str().unwrap()),
+        output_len: None,
+        progress: false,
+    };
+    let result = client.submit(packet, Duration::from_millis(200)).await;
+    assert!(matches!(result, Err(ClientError::Timeout)));
+
+    // Release the worker still waiting on the FIFO.
+    std::fs::write(&fifo, b"").unwrap();
+    std::fs::remove_file(&fifo).unwrap();
+}
+
+#[tokio::test]
+async fn abandoned_requests_are_cancelled() {
+    let fifo = common::fifo("abandoned.fifo");
+    let addr = common::serve(ServerConfig {
+        workers: 1,
+        ..common::fifo_config()
+    });
+
+    let client = Client::builder(addr.to_string()).build();
+    let packet = HashingPacket {
+        algorithm: HashAlgorithms::SHA256,
+        path: local(fifo.to_str().unwrap()),
+        output_len: None,
+        progress: false,
+    };
+    let abandoned = client.submit(packet, Duration::from_secs(60));
+    assert!(tokio::time::timeout(Duration::from_millis(200), abandoned).await.is_err());
+
+    // Cancelling the dropped request frees the only worker.
+    let next = client.hash(local("Cargo.toml"), HashAlgorithms::SHA256);
+    let digest = tokio::time::timeout(Duration::from_secs(5), next).await;
+    assert_eq!(digest.unwrap().unwrap().len(), 64);
+
+    std::fs::write(&fifo, b"").unwrap();
+}
+
+#[tokio::test]
+async fn client_reconnects_after_the_server_drops_it() {
+    // A server answering exactly one request per connection.
+    let addr = common::spawn_server(|listener| async move {
+        loop {
+            let (mut socket, _) = listener.accept().await?;
+            tokio::spawn(async move {
+                let _hello = read_protocol(&mut socket).await.unwrap();
+                let welcome = ProtocolMessage::Welcome(Welcome {
+                    version: PROTOCOL_VERSION,
+                    algorithms: vec![HashAlgorithms::SHA256],
+                    max_packet_size: MAX_PACKET_SIZE as u32,
+                });
+                socket.write_all(&welcome.into_packet().unwrap()).await.unwrap();
+
+                if let Ok(ProtocolMessage::TaskRequest(TaskRequest::HashPacket { id, .. })) =
+                    read_protocol(&mut socket).await
+                {
+                    let response = ProtocolMessage::TaskResponse(TaskResponse::Success {
+                        id,
+                        digest: String::from("cafe"),
+                    });
+                    let _ = socket.write_all(&response.into_packet().unwrap()).await;
+                }
+            });
+        }
+    });
+
+    let client = Client::builder(addr.to_string()).build();
+    for _ in 0..3 {
+        let digest = client.hash(local("any"), HashAlgorithms::SHA256).await.unwrap();
+        assert_eq!(digest, "cafe");
+    }
+}
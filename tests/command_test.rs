@@ -0,0 +1,252 @@
+mod common;
+
+use sha2::{Digest, Sha256};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    auth::Credentials,
+    client::Client,
+    config::{ConfigError, ServerConfig},
+    constants::MAX_PACKET_SIZE,
+    run_server_on,
+};
+
+fn binary() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_task_scheduler"))
+}
+
+fn cargo_toml_digest() -> String {
+    format!("{:x}", Sha256::digest(std::fs::read("Cargo.toml").unwrap()))
+}
+
+#[test]
+fn submit_prints_digests() {
+    let output = binary()
+        .args(["submit", "--server", &common::server_addr().to_string()])
+        .args(["--algorithm", "SHA256", "Cargo.toml"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}  Cargo.toml\n", cargo_toml_digest()));
+}
+
+#[test]
+fn verify_reports_matches_through_exit_code() {
+    let server = common::server_addr().to_string();
+    let run = |expected: &str| {
+        binary()
+            .args(["verify", "--server", &server, "--expected", expected, "Cargo.toml"])
+            .stdout(Stdio::null())
+            .status()
+            .unwrap()
+            .code()
+    };
+
+    assert_eq!(run(&cargo_toml_digest()), Some(0));
+    assert_eq!(run("00"), Some(1));
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Hashes Cargo.toml through the server on `port`, waiting for it to start.
+async fn hash_when_ready(port: u16) -> Option<String> {
+    let client = Client::builder(format!("127.0.0.1:{}", port)).build();
+    for _ in 0..50 {
+        match client.hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256).await {
+            Ok(d) => return Some(d),
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    None
+}
+
+/// Sends SIGTERM, which triggers a graceful shutdown, and waits for the exit.
+fn terminate(server: Child) -> Output {
+    let pid = server.id().to_string();
+    assert!(Command::new("kill").args(["-TERM", &pid]).status().unwrap().success());
+    server.wait_with_output().unwrap()
+}
+
+#[tokio::test]
+async fn serve_reads_its_configuration_file() {
+    let port = free_port();
+    let config = std::env::temp_dir().join(format!("serve-test-{}.toml", std::process::id()));
+    std::fs::write(&config, format!("bind = \"127.0.0.1:{}\"\nworkers = 2\n", port)).unwrap();
+
+    let server = binary()
+        .args(["serve", "--config", config.to_str().unwrap(), "--queue-depth", "8"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let digest = hash_when_ready(port).await;
+    let output = terminate(server);
+    std::fs::remove_file(&config).unwrap();
+    assert_eq!(digest, Some(cargo_toml_digest()));
+    assert!(output.status.success());
+}
+
+#[tokio::test]
+async fn serve_logs_task_spans_as_json() {
+    let port = free_port();
+    let server = binary()
+        .args(["serve", "--bind", &format!("127.0.0.1:{}", port), "--log-format", "json"])
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    assert_eq!(hash_when_ready(port).await, Some(cargo_toml_digest()));
+    let output = terminate(server);
+    assert!(output.status.success());
+
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let completed = events
+        .iter()
+        .find(|e| e["fields"]["message"] == "task completed")
+        .expect("no task completed event");
+    assert_eq!(completed["span"]["algorithm"], "sha256");
+    assert_eq!(completed["span"]["path"], "Cargo.toml");
+    assert_eq!(completed["span"]["outcome"], "success");
+    assert!(completed["span"]["duration_ms"].is_u64());
+    let peer = completed["spans"][0]["peer"].as_str().unwrap();
+    assert!(peer.starts_with("127.0.0.1:"));
+}
+
+#[tokio::test]
+async fn authenticated_identities_are_logged() {
+    let port = free_port();
+    let dir = std::env::temp_dir();
+    let config = dir.join(format!("auth-test-{}.toml", std::process::id()));
+    let key = dir.join(format!("auth-test-{}.key", std::process::id()));
+    let secret = "a sufficiently long secret";
+    std::fs::write(
+        &config,
+        format!("bind = \"127.0.0.1:{}\"\n[auth.keys]\nci = \"{}\"\n", port, secret),
+    )
+    .unwrap();
+    std::fs::write(&key, format!("{}\n", secret)).unwrap();
+
+    let server = binary()
+        .args(["serve", "--config", config.to_str().unwrap(), "--log-format", "json"])
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let client = Client::builder(format!("127.0.0.1:{}", port))
+        .credentials(Credentials::new("ci", secret))
+        .build();
+    let mut ready = false;
+    for _ in 0..50 {
+        if client.welcome().await.is_ok() {
+            ready = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "server did not start");
+
+    let submit = binary()
+        .args(["submit", "--server", &format!("127.0.0.1:{}", port), "Cargo.toml"])
+        .args(["--identity", "ci", "--key-file", key.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let output = terminate(server);
+    std::fs::remove_file(&config).unwrap();
+    std::fs::remove_file(&key).unwrap();
+    assert!(submit.status.success());
+
+    let completed = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|e| e["fields"]["message"] == "task completed")
+        .expect("no task completed event");
+    assert_eq!(completed["spans"][0]["identity"], "ci");
+}
+
+#[test]
+fn configuration_files_are_validated() {
+    let config = ServerConfig::from_toml_str(
+        "workers = 3\n[remote]\nallowed_hosts = [\"example.com\"]\ntimeout = \"2m\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.workers, 3);
+    assert_eq!(config.queue_depth, ServerConfig::default().queue_depth);
+    assert_eq!(config.remote.timeout, Some(Duration::from_secs(120)));
+
+    assert!(matches!(
+        ServerConfig::from_toml_str("workers = 0"),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        ServerConfig::from_toml_str("wrokers = 3"),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        ServerConfig::from_toml_str("[log]\nlevel = \"info,=[\""),
+        Err(ConfigError::Invalid(_))
+    ));
+}
+
+#[test]
+fn builder_applies_the_same_validation() {
+    let config = ServerConfig::builder()
+        .workers(2)
+        .read_timeout(Duration::from_secs(30))
+        .hash_buffer_size(64 * 1024)
+        .build()
+        .unwrap();
+    assert_eq!(config.workers, 2);
+    assert_eq!(config.read_timeout, Duration::from_secs(30));
+    assert_eq!(config.frame_config().read_timeout, Duration::from_secs(30));
+
+    for size in [16, MAX_PACKET_SIZE + 1] {
+        assert!(matches!(
+            ServerConfig::builder().max_packet_size(size).build(),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+    assert!(matches!(
+        ServerConfig::builder().max_in_flight_requests(0).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+
+    let config = ServerConfig::from_toml_str("read_timeout = \"250ms\"\nmax_packet_size = 4096\n").unwrap();
+    assert_eq!(config.read_timeout, Duration::from_millis(250));
+    assert_eq!(config.max_packet_size, 4096);
+}
+
+#[tokio::test]
+async fn servers_refuse_to_start_with_an_invalid_config() {
+    for config in [
+        ServerConfig {
+            queue_depth: 0,
+            ..ServerConfig::default()
+        },
+        ServerConfig {
+            workers: 0,
+            ..ServerConfig::default()
+        },
+    ] {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let error = run_server_on(listener, config, task_scheduler::CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
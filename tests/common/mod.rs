@@ -0,0 +1,155 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+use task_scheduler::{
+    CancellationToken, FilePath, HashAlgorithms,
+    protocol::{
+        HashingPacket, Priority, ProtocolMessage, RequestId, TaskRequest, TaskResponse, client_handshake,
+        read_protocol,
+    },
+    config::ServerConfig,
+    run_server_on,
+    sandbox::SandboxConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Address of a server shared by every test of the current test binary.
+pub fn server_addr() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        let config = ServerConfig {
+            workers: 4,
+            ..ServerConfig::default()
+        };
+        serve(config)
+    })
+}
+
+/// Address of a shared server that also hashes special files, so that tests
+/// can use FIFOs as tasks that only complete once written to.
+pub fn fifo_server_addr() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| serve(fifo_config()))
+}
+
+/// Configuration of a server that hashes FIFOs.
+pub fn fifo_config() -> ServerConfig {
+    let sandbox = SandboxConfig {
+        allow_special_files: true,
+        ..SandboxConfig::default()
+    };
+    ServerConfig::builder().workers(4).sandbox(sandbox).build().unwrap()
+}
+
+/// Creates a FIFO called `name` in a directory private to the test binary,
+/// replacing any left over by a previous run.
+///
+/// Hashing it blocks a worker until something is written to it.
+pub fn fifo(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("task-scheduler-fifos-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fifo = dir.join(name);
+    let _ = std::fs::remove_file(&fifo);
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    fifo
+}
+
+/// Starts a server on an ephemeral port and returns its address.
+///
+/// The server runs on its own thread and runtime so that it outlives the
+/// per-test runtimes created by `#[tokio::test]`.
+pub fn spawn_server<F, Fut>(serve: F) -> SocketAddr
+where
+    F: FnOnce(TcpListener) -> Fut + Send + 'static,
+    Fut: Future<Output = std::io::Result<()>>,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let listener = TcpListener::from_std(listener).unwrap();
+            serve(listener).await
+        })
+    });
+    addr
+}
+
+/// Starts a server running `config` until the test binary exits.
+pub fn serve(config: ServerConfig) -> SocketAddr {
+    spawn_server(|listener| async move {
+        run_server_on(listener, config, CancellationToken::new()).await?;
+        Ok(())
+    })
+}
+
+/// Opens a connection to `addr` and completes the handshake.
+pub async fn connect_to(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    client_handshake(&mut stream).await.unwrap();
+    stream
+}
+
+/// Opens a connection to the shared server and completes the handshake.
+pub async fn connect() -> TcpStream {
+    connect_to(server_addr()).await
+}
+
+/// Builds the frame of a hashing request for a local file.
+pub fn hash_request(id: RequestId, algorithm: HashAlgorithms, path: &str) -> Vec<u8> {
+    prioritized_request(id, algorithm, path, Priority::Normal)
+}
+
+/// Builds the frame of a hashing request for a local file at `priority`.
+pub fn prioritized_request(
+    id: RequestId,
+    algorithm: HashAlgorithms,
+    path: &str,
+    priority: Priority,
+) -> Vec<u8> {
+    ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id,
+        packet: HashingPacket {
+            algorithm,
+            path: FilePath::Local(String::from(path)),
+            output_len: None,
+            progress: false,
+        },
+        priority,
+    })
+    .into_packet()
+    .unwrap()
+}
+
+/// Sends a single request frame and waits for its [`TaskResponse`].
+pub async fn roundtrip(stream: &mut TcpStream, frame: &[u8]) -> TaskResponse {
+    stream.write_all(frame).await.unwrap();
+    match read_protocol(stream).await.unwrap() {
+        ProtocolMessage::TaskResponse(response) => response,
+        other => panic!("Expected a task response, got {:?}", other),
+    }
+}
+
+/// Sends a bare HTTP/1.1 GET and returns the whole response.
+pub async fn http_get(port: u16, path: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+/// Scrapes the metrics endpoint on `port` for the value of the sample `name`,
+/// labels included.
+pub async fn metric(port: u16, name: &str) -> Option<u64> {
+    let body = http_get(port, "/metrics").await.ok()?;
+    body.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use task_scheduler::{
+    config::{ConfigError, ServerConfig},
+    protocol::{ProtocolError, ServerError, client_handshake},
+};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Starts a server with `config` and returns its address and metrics port.
+fn serve(config: ServerConfig) -> (SocketAddr, u16) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = ServerConfig {
+        metrics_bind: Some(format!("127.0.0.1:{}", port)),
+        ..config
+    };
+    (common::serve(config), port)
+}
+
+async fn handshake(stream: &mut TcpStream) -> Result<(), ProtocolError> {
+    client_handshake(stream).await.map(|_| ())
+}
+
+fn assert_refused(result: Result<(), ProtocolError>, expected: ServerError) {
+    match result {
+        Err(ProtocolError::Refused(error)) => assert_eq!(error, expected),
+        other => panic!("Expected {:?}, got {:?}", expected, other),
+    }
+}
+
+#[tokio::test]
+async fn connections_beyond_the_limit_are_refused() {
+    let (addr, port) = serve(ServerConfig::builder().max_connections(2).build().unwrap());
+    let first = common::connect_to(addr).await;
+    let _second = common::connect_to(addr).await;
+
+    let mut third = TcpStream::connect(addr).await.unwrap();
+    assert_refused(handshake(&mut third).await, ServerError::TooManyConnections);
+    let refused = "task_scheduler_refused_connections_total{limit=\"global\"}";
+    assert_eq!(common::metric(port, refused).await, Some(1));
+
+    // The slot of a closed connection is given back.
+    drop(first);
+    for _ in 0..50 {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        if handshake(&mut stream).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("the slot of the closed connection was not released");
+}
+
+#[tokio::test]
+async fn each_address_has_its_own_cap() {
+    let config = ServerConfig::builder().max_connections_per_ip(Some(1)).build().unwrap();
+    let (addr, port) = serve(config);
+    let _first = common::connect_to(addr).await;
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    assert_refused(handshake(&mut second).await, ServerError::TooManyPeerConnections);
+    let refused = "task_scheduler_refused_connections_total{limit=\"per_ip\"}";
+    assert_eq!(common::metric(port, refused).await, Some(1));
+
+    // Linux routes the whole 127.0.0.0/8 block to the loopback interface.
+    if cfg!(target_os = "linux") {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.2:0".parse().unwrap()).unwrap();
+        let mut other = socket.connect(addr).await.unwrap();
+        handshake(&mut other).await.unwrap();
+    }
+}
+
+#[test]
+fn connection_limits_are_validated() {
+    let config =
+        ServerConfig::from_toml_str("max_connections = 10\nmax_connections_per_ip = 2\n").unwrap();
+    assert_eq!(config.max_connections, 10);
+    assert_eq!(config.max_connections_per_ip, Some(2));
+    assert_eq!(ServerConfig::default().max_connections_per_ip, None);
+
+    for invalid in ["max_connections = 0\n", "max_connections_per_ip = 0\n"] {
+        assert!(matches!(
+            ServerConfig::from_toml_str(invalid),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    protocol::{FailureReason, HashingPacket, Priority, ProtocolMessage, TaskRequest, TaskResponse},
+};
+
+fn expect_failure(response: TaskResponse) -> (FailureReason, Option<String>) {
+    match response {
+        TaskResponse::Failed { reason, message, .. } => (reason, message),
+        other => panic!("Expected a failure, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn missing_file_is_reported_as_not_found() {
+    let mut stream = common::connect().await;
+    let frame = common::hash_request(7, HashAlgorithms::SHA256, "/definitely/not/here");
+
+    let response = common::roundtrip(&mut stream, &frame).await;
+    assert_eq!(response.id(), 7);
+    let (reason, message) = expect_failure(response);
+    assert_eq!(reason, FailureReason::NotFound);
+    assert!(!reason.is_retryable());
+    assert!(message.is_some());
+}
+
+#[tokio::test]
+async fn unimplemented_algorithm_is_reported() {
+    let mut stream = common::connect().await;
+    let frame = common::hash_request(8, HashAlgorithms::UNIMPLEMENTED, "Cargo.toml");
+
+    let (reason, _) = expect_failure(common::roundtrip(&mut stream, &frame).await);
+    assert_eq!(reason, FailureReason::UnsupportedAlgorithm);
+}
+
+#[tokio::test]
+async fn remote_path_is_reported_as_disabled_by_default() {
+    let mut stream = common::connect().await;
+    let frame = ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 9,
+        packet: HashingPacket {
+            algorithm: HashAlgorithms::BLAKE3,
+            path: FilePath::Remote(String::from("http://127.0.0.1/file")),
+            output_len: None,
+            progress: false,
+        },
+        priority: Priority::Normal,
+    })
+    .into_packet()
+    .unwrap();
+
+    let (reason, _) = expect_failure(common::roundtrip(&mut stream, &frame).await);
+    assert_eq!(reason, FailureReason::RemoteDisabled);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use bytes::BytesMut;
+use futures_util::{SinkExt, StreamExt};
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    protocol::{
+        HashingPacket, Hello, Priority, ProtocolCodec, ProtocolError, ProtocolMessage,
+        TaskRequest, TaskResponse, read_protocol, write_protocol,
+    },
+};
+use tokio::net::TcpStream;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+fn request(id: u64) -> ProtocolMessage {
+    ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id,
+        packet: HashingPacket {
+            algorithm: HashAlgorithms::SHA256,
+            path: FilePath::Local(String::from("Cargo.toml")),
+            output_len: None,
+            progress: false,
+        },
+        priority: Priority::Normal,
+    })
+}
+
+#[tokio::test]
+async fn frames_round_trip_over_an_in_memory_pipe() {
+    let (mut client, mut server) = tokio::io::duplex(64);
+
+    let writer = tokio::spawn(async move {
+        for id in 0..3 {
+            write_protocol(&mut client, &request(id)).await.unwrap();
+        }
+    });
+    for expected in 0..3 {
+        match read_protocol(&mut server).await.unwrap() {
+            ProtocolMessage::TaskRequest(task) => assert_eq!(task.id(), expected),
+            other => panic!("Expected a task request, got {:?}", other),
+        }
+    }
+    writer.await.unwrap();
+}
+
+#[test]
+fn codec_matches_the_stream_framing() {
+    let message = request(7);
+    let mut encoded = BytesMut::new();
+    ProtocolCodec::default().encode(&message, &mut encoded).unwrap();
+    assert_eq!(&encoded[..], &message.into_packet().unwrap()[..]);
+
+    // Frames arriving a byte at a time are only decoded once complete.
+    let mut codec = ProtocolCodec::default();
+    let mut buffer = BytesMut::new();
+    let (last, head) = encoded.split_last().unwrap();
+    for byte in head {
+        buffer.extend_from_slice(&[*byte]);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+    }
+    buffer.extend_from_slice(&[*last]);
+    match codec.decode(&mut buffer).unwrap() {
+        Some(ProtocolMessage::TaskRequest(task)) => assert_eq!(task.id(), 7),
+        other => panic!("Expected a task request, got {:?}", other),
+    }
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn codec_enforces_its_size_limit() {
+    let mut codec = ProtocolCodec::new(16);
+
+    let mut oversized = BytesMut::from(&[0u8, 0, 0, 17][..]);
+    assert!(matches!(
+        codec.decode(&mut oversized),
+        Err(ProtocolError::PacketTooLarge(17))
+    ));
+
+    let mut dst = BytesMut::new();
+    assert!(codec.encode(request(1), &mut dst).is_err());
+    assert!(dst.is_empty());
+}
+
+#[tokio::test]
+async fn framed_codec_talks_to_the_server() {
+    let stream = TcpStream::connect(common::server_addr()).await.unwrap();
+    let mut framed = Framed::new(stream, ProtocolCodec::default());
+
+    framed.send(ProtocolMessage::Hello(Hello::new())).await.unwrap();
+    match framed.next().await {
+        Some(Ok(ProtocolMessage::Welcome(_))) => {}
+        other => panic!("Expected a welcome, got {:?}", other),
+    }
+
+    framed.send(request(3)).await.unwrap();
+    match framed.next().await {
+        Some(Ok(ProtocolMessage::TaskResponse(TaskResponse::Success { id: 3, .. }))) => {}
+        other => panic!("Expected a successful response, got {:?}", other),
+    }
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    config::ServerConfig,
+    constants::{MAX_PACKET_SIZE, PROTOCOL_VERSION},
+    protocol::{
+        Hello, HashingPacket, Priority, ProtocolMessage, ServerError, TaskRequest, client_handshake,
+        read_protocol,
+    },
+};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn welcome_advertises_capabilities() {
+    let mut stream = TcpStream::connect(common::server_addr()).await.unwrap();
+    let welcome = client_handshake(&mut stream).await.unwrap();
+
+    assert_eq!(welcome.version, PROTOCOL_VERSION);
+    assert_eq!(welcome.max_packet_size as usize, MAX_PACKET_SIZE);
+    assert!(welcome.algorithms.contains(&HashAlgorithms::SHA256));
+    assert!(!welcome.algorithms.contains(&HashAlgorithms::UNIMPLEMENTED));
+}
+
+#[tokio::test]
+async fn mismatched_version_is_refused() {
+    let mut stream = TcpStream::connect(common::server_addr()).await.unwrap();
+    let hello = ProtocolMessage::Hello(Hello { version: PROTOCOL_VERSION + 1 });
+    stream.write_all(&hello.into_packet().unwrap()).await.unwrap();
+
+    match read_protocol(&mut stream).await.unwrap() {
+        ProtocolMessage::Error(ServerError::VersionMismatch { server, client }) => {
+            assert_eq!(server, PROTOCOL_VERSION);
+            assert_eq!(client, PROTOCOL_VERSION + 1);
+        }
+        other => panic!("Expected a version mismatch, got {:?}", other),
+    }
+    assert!(read_protocol(&mut stream).await.is_err());
+}
+
+#[tokio::test]
+async fn task_request_before_hello_is_refused() {
+    let mut stream = TcpStream::connect(common::server_addr()).await.unwrap();
+    let task = ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 1,
+        packet: HashingPacket {
+            algorithm: HashAlgorithms::SHA256,
+            path: FilePath::Local(String::from("Cargo.toml")),
+            output_len: None,
+            progress: false,
+        },
+        priority: Priority::Normal,
+    });
+    stream.write_all(&task.into_packet().unwrap()).await.unwrap();
+
+    match read_protocol(&mut stream).await.unwrap() {
+        ProtocolMessage::Error(ServerError::HandshakeRequired) => {}
+        other => panic!("Expected a handshake refusal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn configured_frame_limit_is_advertised_and_enforced() {
+    let config = ServerConfig::builder().max_packet_size(1024).build().unwrap();
+    let addr = common::serve(config);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let welcome = client_handshake(&mut stream).await.unwrap();
+    assert_eq!(welcome.max_packet_size, 1024);
+
+    let long_path = "x".repeat(2048);
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, &long_path);
+    stream.write_all(&frame).await.unwrap();
+    assert!(read_protocol(&mut stream).await.is_err());
+}
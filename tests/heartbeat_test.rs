@@ -0,0 +1,155 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    client::Client,
+    config::{ConfigError, ServerConfig},
+    protocol::{ProtocolMessage, TaskResponse, read_protocol, write_protocol},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A server pinging after 100ms of silence and hanging up after 400ms, which
+/// also hashes FIFOs.
+fn server() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        let config = ServerConfig {
+            read_timeout: Duration::from_millis(200),
+            heartbeat_interval: Duration::from_millis(100),
+            idle_timeout: Duration::from_millis(400),
+            ..common::fifo_config()
+        };
+        common::serve(config)
+    })
+}
+
+#[tokio::test]
+async fn clients_answering_pings_stay_connected() {
+    let mut stream = common::connect_to(server()).await;
+    let started = Instant::now();
+    let mut pings = 0;
+    while started.elapsed() < Duration::from_secs(1) {
+        match read_protocol(&mut stream).await.unwrap() {
+            ProtocolMessage::Ping(token) => {
+                pings += 1;
+                write_protocol(&mut stream, &ProtocolMessage::Pong(token)).await.unwrap();
+            }
+            other => panic!("Expected a ping, got {:?}", other),
+        }
+    }
+    assert!(pings >= 3, "only {} pings", pings);
+
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, "Cargo.toml");
+    assert!(matches!(
+        common::roundtrip(&mut stream, &frame).await,
+        TaskResponse::Success { id: 1, .. }
+    ));
+}
+
+#[tokio::test]
+async fn silent_peers_are_disconnected() {
+    let mut stream = common::connect_to(server()).await;
+    let started = Instant::now();
+    loop {
+        match read_protocol(&mut stream).await {
+            Ok(ProtocolMessage::Ping(_)) => {}
+            Ok(other) => panic!("Expected a ping, got {:?}", other),
+            Err(e) => {
+                assert!(e.is_disconnect(), "unexpected error {:?}", e);
+                break;
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(400), "closed after {:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(3), "closed after {:?}", elapsed);
+}
+
+#[tokio::test]
+async fn trickled_frames_are_cut_off() {
+    let mut stream = common::connect_to(server()).await;
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, "Cargo.toml");
+    stream.write_all(&frame[..2]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let _ = stream.write_all(&frame[2..]).await;
+
+    let mut buf = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(3), stream.read_to_end(&mut buf)).await;
+    assert!(read.is_ok(), "connection was kept open");
+    assert!(buf.is_empty(), "the trickled request was served");
+}
+
+#[tokio::test]
+async fn the_server_answers_pings() {
+    let mut stream = common::connect_to(server()).await;
+    write_protocol(&mut stream, &ProtocolMessage::Ping(42)).await.unwrap();
+    assert!(matches!(
+        read_protocol(&mut stream).await.unwrap(),
+        ProtocolMessage::Pong(42)
+    ));
+}
+
+#[tokio::test]
+async fn long_tasks_outlive_the_idle_timeout() {
+    let fifo = common::fifo("slow.fifo");
+
+    let writer = fifo.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        std::fs::write(writer, b"eventually").unwrap();
+    });
+
+    let client = Client::builder(server().to_string()).retries(0).build();
+    let path = FilePath::Local(fifo.to_str().unwrap().to_owned());
+    let digest = client.hash(path, HashAlgorithms::SHA256).await.unwrap();
+    assert_eq!(digest.len(), 64);
+}
+
+#[tokio::test]
+async fn ping_floods_do_not_hold_up_the_workers() {
+    let addr = common::serve(ServerConfig {
+        workers: 1,
+        ..common::fifo_config()
+    });
+    let fifo = common::fifo("flood.fifo");
+
+    // A peer pipelining a task, then pinging without ever reading the pongs,
+    // until the socket buffers and its outbound queue are full.
+    let mut flooder = common::connect_to(addr).await;
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, fifo.to_str().unwrap());
+    flooder.write_all(&frame).await.unwrap();
+    let mut ping = Vec::new();
+    write_protocol(&mut ping, &ProtocolMessage::Ping(0)).await.unwrap();
+    let pings = ping.repeat(1_000_000);
+    let flood = tokio::time::timeout(Duration::from_secs(10), flooder.write_all(&pings));
+    flood.await.expect("the server stopped reading").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Its task completes, and the only worker must move on to the next one.
+    tokio::task::spawn_blocking(move || std::fs::write(fifo, b"done").unwrap());
+    let mut other = common::connect_to(addr).await;
+    let frame = common::hash_request(2, HashAlgorithms::SHA256, "Cargo.toml");
+    let response = tokio::time::timeout(Duration::from_secs(5), common::roundtrip(&mut other, &frame));
+    assert!(matches!(response.await, Ok(TaskResponse::Success { id: 2, .. })));
+    drop(flooder);
+}
+
+#[test]
+fn heartbeat_settings_are_validated() {
+    let config = ServerConfig::from_toml_str("heartbeat_interval = \"5s\"\nidle_timeout = \"20s\"\n").unwrap();
+    assert_eq!(config.frame_config().idle_timeout, Some(Duration::from_secs(5)));
+    assert_eq!(config.idle_timeout, Duration::from_secs(20));
+
+    for (heartbeat, idle) in [(0, 10), (10, 10), (20, 10)] {
+        assert!(matches!(
+            ServerConfig::builder()
+                .heartbeat_interval(Duration::from_secs(heartbeat))
+                .idle_timeout(Duration::from_secs(idle))
+                .build(),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+}
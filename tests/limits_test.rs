@@ -0,0 +1,82 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::time::{Duration, Instant};
+use task_scheduler::{
+    HashAlgorithms,
+    config::{ConfigError, ServerConfig},
+    protocol::{FailureReason, TaskResponse},
+    sandbox::SandboxConfig,
+};
+
+/// A single-worker server hashing special files, so that `/dev/zero` can
+/// stand in for an endless input.
+fn serve(task_timeout: Option<Duration>, max_input_size: Option<u64>) -> std::net::SocketAddr {
+    let sandbox = SandboxConfig {
+        allow_special_files: true,
+        ..SandboxConfig::default()
+    };
+    let config = ServerConfig::builder()
+        .workers(1)
+        .sandbox(sandbox)
+        .task_timeout(task_timeout)
+        .max_input_size(max_input_size)
+        .build()
+        .unwrap();
+    common::serve(config)
+}
+
+async fn hash(addr: std::net::SocketAddr, path: &str) -> TaskResponse {
+    let mut stream = common::connect_to(addr).await;
+    common::roundtrip(&mut stream, &common::hash_request(1, HashAlgorithms::SHA256, path)).await
+}
+
+fn assert_failed(response: TaskResponse, expected: FailureReason) {
+    match response {
+        TaskResponse::Failed { reason, .. } if reason == expected => {}
+        other => panic!("Expected a {:?} failure, got {:?}", expected, other),
+    }
+}
+
+#[tokio::test]
+async fn tasks_past_their_deadline_are_cancelled() {
+    let addr = serve(Some(Duration::from_millis(200)), None);
+
+    let started = Instant::now();
+    assert_failed(hash(addr, "/dev/zero").await, FailureReason::Timeout);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // The only worker is free again for the next task.
+    assert!(matches!(hash(addr, "Cargo.toml").await, TaskResponse::Success { .. }));
+}
+
+#[tokio::test]
+async fn inputs_over_the_size_limit_are_refused() {
+    let limit = std::fs::metadata("Cargo.toml").unwrap().len();
+    let addr = serve(None, Some(1024));
+    assert_failed(hash(addr, "/dev/zero").await, FailureReason::TooLarge);
+
+    let addr = serve(None, Some(limit - 1));
+    assert_failed(hash(addr, "Cargo.toml").await, FailureReason::TooLarge);
+
+    let addr = serve(None, Some(limit));
+    assert!(matches!(hash(addr, "Cargo.toml").await, TaskResponse::Success { .. }));
+}
+
+#[test]
+fn task_limits_are_validated() {
+    let config = ServerConfig::from_toml_str("task_timeout = \"2m\"\nmax_input_size = 4096\n").unwrap();
+    assert_eq!(config.task_timeout, Some(Duration::from_secs(120)));
+    assert_eq!(config.max_input_size, Some(4096));
+    assert_eq!(ServerConfig::default().max_input_size, None);
+
+    assert!(matches!(
+        ServerConfig::builder().task_timeout(Some(Duration::ZERO)).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        ServerConfig::builder().max_input_size(Some(0)).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+}
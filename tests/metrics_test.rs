@@ -0,0 +1,69 @@
+mod common;
+
+use std::time::Duration;
+use task_scheduler::{
+    HashAlgorithms,
+    config::ServerConfig,
+    protocol::{FailureReason, TaskResponse},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn metrics_endpoint_reports_tasks_failures_and_errors() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = ServerConfig::builder()
+        .workers(2)
+        .metrics_bind(format!("127.0.0.1:{}", port))
+        .build()
+        .unwrap();
+    let addr = common::serve(config);
+
+    let mut stream = common::connect_to(addr).await;
+    let ok = common::hash_request(1, HashAlgorithms::SHA256, "Cargo.toml");
+    assert!(matches!(common::roundtrip(&mut stream, &ok).await, TaskResponse::Success { .. }));
+    let missing = common::hash_request(2, HashAlgorithms::BLAKE3, "/definitely/missing");
+    assert!(matches!(
+        common::roundtrip(&mut stream, &missing).await,
+        TaskResponse::Failed { reason: FailureReason::NotFound, .. }
+    ));
+
+    // A header announcing a 2 GiB frame is a protocol error.
+    let mut junk = TcpStream::connect(addr).await.unwrap();
+    junk.write_all(&[0x7f, 0xff, 0xff, 0xff]).await.unwrap();
+    let _ = junk.read(&mut [0u8; 16]).await;
+
+    let expected = [
+        "task_scheduler_tasks_total{algorithm=\"sha256\"} 1\n".to_owned(),
+        "task_scheduler_tasks_total{algorithm=\"blake3\"} 1\n".to_owned(),
+        "task_scheduler_task_failures_total{reason=\"not_found\"} 1\n".to_owned(),
+        "task_scheduler_protocol_errors_total{kind=\"packet_too_large\"} 1\n".to_owned(),
+        format!(
+            "task_scheduler_bytes_hashed_total {}\n",
+            std::fs::metadata("Cargo.toml").unwrap().len()
+        ),
+        "task_scheduler_task_latency_seconds_count 2\n".to_owned(),
+        "task_scheduler_active_connections 1\n".to_owned(),
+    ];
+
+    let mut body = String::new();
+    for _ in 0..50 {
+        body = common::http_get(port, "/metrics").await.unwrap_or_default();
+        if expected.iter().all(|line| body.contains(line.as_str())) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(body.starts_with("HTTP/1.1 200 OK\r\n"), "{}", body);
+    for line in &expected {
+        assert!(body.contains(line.as_str()), "missing {:?} in\n{}", line, body);
+    }
+    assert!(body.contains("# TYPE task_scheduler_task_latency_seconds histogram\n"));
+
+    let not_found = common::http_get(port, "/other").await.unwrap();
+    assert!(not_found.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::collections::HashSet;
+use task_scheduler::{
+    HashAlgorithms,
+    protocol::{FailureReason, ProtocolMessage, TaskResponse, read_protocol, write_protocol},
+};
+use tokio::io::AsyncWriteExt;
+
+async fn next_response(stream: &mut tokio::net::TcpStream) -> TaskResponse {
+    match read_protocol(stream).await.unwrap() {
+        ProtocolMessage::TaskResponse(response) => response,
+        other => panic!("Expected a task response, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn pipelined_requests_are_all_answered() {
+    let mut stream = common::connect().await;
+
+    let mut frames = Vec::new();
+    for id in 0..300 {
+        frames.extend(common::hash_request(id, HashAlgorithms::SHA256, "Cargo.toml"));
+    }
+    stream.write_all(&frames).await.unwrap();
+
+    let mut seen = HashSet::new();
+    for _ in 0..300 {
+        match next_response(&mut stream).await {
+            TaskResponse::Success { id, .. } => assert!(seen.insert(id)),
+            other => panic!("Unexpected failure: {:?}", other),
+        }
+    }
+    assert_eq!(seen, (0..300).collect());
+}
+
+#[tokio::test]
+async fn slow_request_does_not_block_later_ones() {
+    let fifo = common::fifo("slow.fifo");
+
+    let mut stream = common::connect_to(common::fifo_server_addr()).await;
+    let slow = common::hash_request(1, HashAlgorithms::SHA256, fifo.to_str().unwrap());
+    let fast = common::hash_request(2, HashAlgorithms::SHA256, "Cargo.toml");
+    stream.write_all(&slow).await.unwrap();
+    stream.write_all(&fast).await.unwrap();
+
+    // The FIFO has no writer yet, so request 1 cannot complete first.
+    assert_eq!(next_response(&mut stream).await.id(), 2);
+
+    std::fs::write(&fifo, b"finally").unwrap();
+    let response = next_response(&mut stream).await;
+    assert_eq!(response.id(), 1);
+    assert!(matches!(response, TaskResponse::Success { .. }));
+
+    std::fs::remove_file(&fifo).unwrap();
+}
+
+#[tokio::test]
+async fn ids_already_in_flight_are_refused() {
+    let fifo = common::fifo("duplicate.fifo");
+    let mut stream = common::connect_to(common::fifo_server_addr()).await;
+    let slow = common::hash_request(1, HashAlgorithms::SHA256, fifo.to_str().unwrap());
+    stream.write_all(&slow).await.unwrap();
+
+    let duplicate = common::hash_request(1, HashAlgorithms::SHA256, "Cargo.toml");
+    assert!(matches!(
+        common::roundtrip(&mut stream, &duplicate).await,
+        TaskResponse::Failed {
+            id: 1,
+            reason: FailureReason::InvalidRequest,
+            ..
+        }
+    ));
+
+    // The request in flight can still be cancelled.
+    write_protocol(&mut stream, &ProtocolMessage::CancelTask(1)).await.unwrap();
+    assert!(matches!(
+        next_response(&mut stream).await,
+        TaskResponse::Failed {
+            id: 1,
+            reason: FailureReason::Cancelled,
+            ..
+        }
+    ));
+
+    std::fs::write(&fifo, b"").unwrap();
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use task_scheduler::{
+    HashAlgorithms,
+    config::{ConfigError, ServerConfig},
+    protocol::{Priority, ProtocolMessage, TaskResponse, read_protocol},
+    queue::SchedulingConfig,
+};
+use tokio::io::AsyncWriteExt;
+
+/// A 1 MiB file, slow enough to hash that responses arrive in the order
+/// their tasks ran.
+fn input() -> &'static str {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("priority-test-{}", std::process::id()));
+        std::fs::write(&path, vec![0x5a; 1 << 20]).unwrap();
+        path.to_str().unwrap().to_owned()
+    })
+}
+
+/// Queues `tasks` behind one blocking the only worker, in the given order,
+/// and returns the priorities of the tasks in the order they ran.
+async fn run(name: &str, scheduling: SchedulingConfig, tasks: &[Priority]) -> Vec<Priority> {
+    let addr = common::serve(ServerConfig {
+        workers: 1,
+        scheduling,
+        ..common::fifo_config()
+    });
+
+    let fifo = common::fifo(name);
+
+    let mut stream = common::connect_to(addr).await;
+    stream
+        .write_all(&common::hash_request(0, HashAlgorithms::SHA256, fifo.to_str().unwrap()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut priorities = HashMap::new();
+    for (id, priority) in (1..).zip(tasks) {
+        priorities.insert(id, *priority);
+        stream
+            .write_all(&common::prioritized_request(id, HashAlgorithms::SHA256, input(), *priority))
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    tokio::task::spawn_blocking(move || std::fs::write(fifo, b"done").unwrap());
+    let mut order = Vec::new();
+    for _ in 0..=tasks.len() {
+        match read_protocol(&mut stream).await.unwrap() {
+            ProtocolMessage::TaskResponse(TaskResponse::Success { id, .. }) => {
+                // The task blocking the worker is not part of the race.
+                if id != 0 {
+                    order.push(priorities[&id]);
+                }
+            }
+            other => panic!("Expected a success, got {:?}", other),
+        }
+    }
+    order
+}
+
+#[tokio::test]
+async fn interactive_tasks_jump_ahead_of_bulk_ones() {
+    use Priority::*;
+    assert_eq!(
+        run("jump", SchedulingConfig::default(), &[Bulk, Bulk, Normal, Interactive]).await,
+        [Interactive, Normal, Bulk, Bulk]
+    );
+}
+
+#[tokio::test]
+async fn passed_over_levels_are_eventually_served() {
+    use Priority::*;
+    let scheduling = SchedulingConfig {
+        starvation_limit: 1,
+        ..SchedulingConfig::default()
+    };
+    assert_eq!(
+        run("starvation", scheduling, &[Bulk, Interactive, Interactive, Interactive]).await,
+        [Interactive, Bulk, Interactive, Interactive]
+    );
+}
+
+#[test]
+fn priorities_and_starvation_limit_are_parsed() {
+    for priority in Priority::ALL {
+        assert_eq!(priority.name().parse::<Priority>().unwrap(), *priority);
+    }
+    assert_eq!(Priority::default(), Priority::Normal);
+    assert!("urgent".parse::<Priority>().is_err());
+
+    let config = ServerConfig::from_toml_str("[scheduling]\nstarvation_limit = 3\n").unwrap();
+    assert_eq!(config.scheduling.starvation_limit, 3);
+    assert!(matches!(
+        ServerConfig::from_toml_str("[scheduling]\nstarvation_limit = 0\n"),
+        Err(ConfigError::Invalid(_))
+    ));
+}
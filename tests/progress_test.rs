@@ -0,0 +1,111 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    client::Client,
+    config::{ConfigError, ServerConfig},
+    protocol::{
+        HashingPacket, Priority, Progress, ProtocolMessage, TaskRequest, TaskResponse, read_protocol,
+    },
+};
+use tokio::io::AsyncWriteExt;
+
+const SIZE: u64 = 16 << 20;
+
+/// A 16 MiB file, large enough to span several progress intervals.
+fn large_file() -> &'static str {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("progress-test-{}", std::process::id()));
+        std::fs::write(&path, vec![0x5a; SIZE as usize]).unwrap();
+        path.to_str().unwrap().to_owned()
+    })
+}
+
+fn server() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        let config = ServerConfig::builder()
+            .workers(2)
+            .progress_interval(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        common::serve(config)
+    })
+}
+
+fn packet(progress: bool) -> HashingPacket {
+    HashingPacket {
+        algorithm: HashAlgorithms::SHA256,
+        path: FilePath::Local(large_file().to_owned()),
+        output_len: None,
+        progress,
+    }
+}
+
+/// Sends one request and collects the progress frames preceding its response.
+async fn run(progress: bool) -> (Vec<Progress>, TaskResponse) {
+    let mut stream = common::connect_to(server()).await;
+    let request = ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 3,
+        packet: packet(progress),
+        priority: Priority::Normal,
+    });
+    stream.write_all(&request.into_packet().unwrap()).await.unwrap();
+
+    let mut reports = Vec::new();
+    loop {
+        match read_protocol(&mut stream).await.unwrap() {
+            ProtocolMessage::Progress(progress) => reports.push(progress),
+            ProtocolMessage::TaskResponse(response) => return (reports, response),
+            other => panic!("Unexpected frame {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn progress_is_reported_when_requested() {
+    let (reports, response) = run(true).await;
+    assert!(matches!(response, TaskResponse::Success { id: 3, .. }));
+    assert!(!reports.is_empty());
+    for pair in reports.windows(2) {
+        assert!(pair[0].bytes_processed <= pair[1].bytes_processed);
+    }
+    for report in &reports {
+        assert_eq!(report.id, 3);
+        assert_eq!(report.total_bytes, Some(SIZE));
+        assert!(report.bytes_processed <= SIZE);
+        assert!(report.bytes_per_second > 0);
+    }
+
+    let (reports, response) = run(false).await;
+    assert!(matches!(response, TaskResponse::Success { .. }));
+    assert!(reports.is_empty());
+}
+
+#[tokio::test]
+async fn clients_receive_progress_callbacks() {
+    let client = Client::builder(server().to_string()).build();
+    let deadline = Duration::from_secs(60);
+    let mut reports = Vec::new();
+    let digest = client
+        .submit_with_progress(packet(false), deadline, |p| reports.push(p.clone()))
+        .await
+        .unwrap();
+
+    assert!(!reports.is_empty());
+    assert_eq!(digest, client.submit(packet(false), deadline).await.unwrap());
+}
+
+#[test]
+fn progress_interval_is_validated() {
+    let config = ServerConfig::from_toml_str("progress_interval = \"250ms\"\n").unwrap();
+    assert_eq!(config.progress_interval, Duration::from_millis(250));
+    assert!(matches!(
+        ServerConfig::builder().progress_interval(Duration::ZERO).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    auth::{AuthConfig, Credentials},
+    client::{Client, ClientError},
+    config::{ConfigError, ServerConfig},
+    protocol::{ProtocolMessage, QuotaError, TaskResponse, read_protocol},
+    quota::{Quota, QuotaConfig},
+};
+use tokio::io::AsyncWriteExt;
+
+const KEY: &str = "correct horse battery staple";
+
+/// A quota allowing bursts of two requests, refilled every ten seconds.
+fn slow() -> Quota {
+    Quota {
+        rate: Some(0.1),
+        burst: Some(2),
+        max_in_flight: None,
+    }
+}
+
+#[tokio::test]
+async fn requests_beyond_the_rate_are_refused() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let quotas = QuotaConfig {
+        default: slow(),
+        ..QuotaConfig::default()
+    };
+    let config = ServerConfig::builder()
+        .quotas(quotas)
+        .metrics_bind(format!("127.0.0.1:{}", port))
+        .build()
+        .unwrap();
+    let addr = common::serve(config);
+
+    // Both connections come from the same address and share its bucket.
+    let mut first = common::connect_to(addr).await;
+    let mut second = common::connect_to(addr).await;
+    for (stream, id) in [(&mut first, 1), (&mut second, 2)] {
+        let frame = common::hash_request(id, HashAlgorithms::SHA256, "Cargo.toml");
+        assert!(matches!(common::roundtrip(stream, &frame).await, TaskResponse::Success { .. }));
+    }
+
+    let frame = common::hash_request(3, HashAlgorithms::SHA256, "Cargo.toml");
+    match common::roundtrip(&mut first, &frame).await {
+        TaskResponse::QuotaExceeded {
+            id: 3,
+            error: QuotaError::RateLimited { retry_after },
+        } => assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(10)),
+        other => panic!("Expected a rate limit, got {:?}", other),
+    }
+    let rejections = "task_scheduler_quota_rejections_total{kind=\"rate_limited\"}";
+    assert_eq!(common::metric(port, rejections).await, Some(1));
+}
+
+#[tokio::test]
+async fn in_flight_quotas_span_connections() {
+    let quotas = QuotaConfig {
+        default: Quota {
+            max_in_flight: Some(1),
+            ..Quota::default()
+        },
+        ..QuotaConfig::default()
+    };
+    let addr = common::serve(ServerConfig {
+        quotas,
+        ..common::fifo_config()
+    });
+
+    let fifo = common::fifo("running");
+
+    let mut running = common::connect_to(addr).await;
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, fifo.to_str().unwrap());
+    running.write_all(&frame).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut other = common::connect_to(addr).await;
+    let frame = common::hash_request(2, HashAlgorithms::SHA256, "Cargo.toml");
+    assert!(matches!(
+        common::roundtrip(&mut other, &frame).await,
+        TaskResponse::QuotaExceeded {
+            id: 2,
+            error: QuotaError::TooManyInFlight { limit: 1 },
+        }
+    ));
+
+    tokio::task::spawn_blocking(move || std::fs::write(fifo, b"done").unwrap());
+    match read_protocol(&mut running).await.unwrap() {
+        ProtocolMessage::TaskResponse(TaskResponse::Success { id: 1, .. }) => {}
+        other => panic!("Expected the FIFO digest, got {:?}", other),
+    }
+    let frame = common::hash_request(3, HashAlgorithms::SHA256, "Cargo.toml");
+    assert!(matches!(
+        common::roundtrip(&mut other, &frame).await,
+        TaskResponse::Success { id: 3, .. }
+    ));
+}
+
+#[tokio::test]
+async fn identities_get_the_quota_of_their_tier() {
+    let auth = AuthConfig::default().with_key("ci", KEY).with_key("guest", KEY);
+    let quotas = QuotaConfig {
+        default: slow(),
+        ..QuotaConfig::default()
+    }
+    .with_tier("unlimited", Quota::default())
+    .with_identity("ci", "unlimited");
+    let config = ServerConfig::builder().auth(auth).quotas(quotas).build().unwrap();
+    let addr = common::serve(config);
+
+    let client = |identity| {
+        Client::builder(addr.to_string())
+            .credentials(Credentials::new(identity, KEY))
+            .build()
+    };
+    let path = || FilePath::Local(String::from("Cargo.toml"));
+
+    let ci = client("ci");
+    for _ in 0..5 {
+        ci.hash(path(), HashAlgorithms::SHA256).await.unwrap();
+    }
+
+    let guest = client("guest");
+    for _ in 0..2 {
+        guest.hash(path(), HashAlgorithms::SHA256).await.unwrap();
+    }
+    let error = guest.hash(path(), HashAlgorithms::SHA256).await.unwrap_err();
+    assert!(error.is_retryable());
+    assert!(matches!(
+        error,
+        ClientError::QuotaExceeded(QuotaError::RateLimited { .. })
+    ));
+}
+
+#[test]
+fn quota_settings_are_validated() {
+    let config = ServerConfig::from_toml_str(
+        "[quotas.default]\nrate = 5.0\nmax_in_flight = 4\n\n\
+         [quotas.tiers.batch]\nrate = 100.0\nburst = 500\n\n\
+         [quotas.identities]\nci = \"batch\"\n\n\
+         [quotas.addresses]\n\"10.0.0.12\" = \"batch\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.quotas.default.max_in_flight, Some(4));
+    assert_eq!(config.quotas.tiers["batch"].burst, Some(500));
+    assert_eq!(config.quotas.addresses[&"10.0.0.12".parse().unwrap()], "batch");
+
+    for invalid in [
+        "[quotas.identities]\nci = \"missing\"\n",
+        "[quotas.default]\nrate = 0.0\n",
+        "[quotas.default]\nburst = 5\n",
+        "[quotas.tiers.batch]\nmax_in_flight = 0\n",
+    ] {
+        assert!(matches!(
+            ServerConfig::from_toml_str(invalid),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+    assert!(ServerConfig::from_toml_str("[quotas.addresses]\n\"not an ip\" = \"batch\"\n").is_err());
+}
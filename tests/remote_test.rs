@@ -0,0 +1,152 @@
+mod common;
+
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    protocol::{FailureReason, HashingPacket, Priority, ProtocolMessage, TaskRequest, TaskResponse},
+    config::ServerConfig,
+    remote::RemoteConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const BODY: &[u8] = b"bytes served over plain HTTP";
+const MAX_SIZE: u64 = 64 * 1024;
+
+/// A minimal HTTP/1.1 server answering a fixed set of routes.
+fn http_addr() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        common::spawn_server(|listener| async move {
+            loop {
+                let (mut socket, _) = listener.accept().await?;
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut byte = [0u8; 1];
+                    while !head.ends_with(b"\r\n\r\n") {
+                        if socket.read(&mut byte).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8_lossy(&head);
+                    let path = head.split_whitespace().nth(1).unwrap_or("/").to_owned();
+
+                    let reply = match path.as_str() {
+                        "/data" => ok_response(BODY),
+                        "/redirect" => redirect("/data"),
+                        "/loop" => redirect("/loop"),
+                        "/offsite" => redirect("http://forbidden.invalid/data"),
+                        "/huge" => {
+                            // No Content-Length: the limit must be enforced while streaming.
+                            let mut reply =
+                                b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n".to_vec();
+                            reply.extend(vec![b'x'; MAX_SIZE as usize * 2]);
+                            reply
+                        }
+                        _ => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec(),
+                    };
+                    let _ = socket.write_all(&reply).await;
+                });
+            }
+        })
+    })
+}
+
+fn ok_response(body: &[u8]) -> Vec<u8> {
+    let mut reply = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    reply.extend_from_slice(body);
+    reply
+}
+
+fn redirect(location: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        location
+    )
+    .into_bytes()
+}
+
+/// An orchestrator allowed to download from 127.0.0.1 only.
+fn orchestrator_addr() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        let remote = RemoteConfig {
+            allowed_hosts: vec![String::from("127.0.0.1")],
+            timeout: Some(Duration::from_secs(5)),
+            max_size: Some(MAX_SIZE),
+            max_redirects: 3,
+            ..RemoteConfig::default()
+        };
+        let config = ServerConfig {
+            workers: 2,
+            remote,
+            ..ServerConfig::default()
+        };
+        common::serve(config)
+    })
+}
+
+async fn hash_url(url: String) -> TaskResponse {
+    let mut stream = common::connect_to(orchestrator_addr()).await;
+    let frame = ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 1,
+        packet: HashingPacket {
+            algorithm: HashAlgorithms::SHA256,
+            path: FilePath::Remote(url),
+            output_len: None,
+            progress: false,
+        },
+        priority: Priority::Normal,
+    })
+    .into_packet()
+    .unwrap();
+    common::roundtrip(&mut stream, &frame).await
+}
+
+fn failure_reason(response: TaskResponse) -> FailureReason {
+    match response {
+        TaskResponse::Failed { reason, .. } => reason,
+        other => panic!("Expected a failure, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn remote_body_is_hashed() {
+    let expected = format!("{:x}", Sha256::digest(BODY));
+    for route in ["data", "redirect"] {
+        let url = format!("http://{}/{}", http_addr(), route);
+        match hash_url(url).await {
+            TaskResponse::Success { digest, .. } => assert_eq!(digest, expected),
+            other => panic!("Expected a digest for /{}, got {:?}", route, other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn hosts_outside_the_allowlist_are_refused() {
+    let url = format!("http://localhost:{}/data", http_addr().port());
+    assert_eq!(failure_reason(hash_url(url).await), FailureReason::RemoteNotAllowed);
+
+    let url = format!("http://{}/offsite", http_addr());
+    assert_eq!(failure_reason(hash_url(url).await), FailureReason::RemoteNotAllowed);
+}
+
+#[tokio::test]
+async fn remote_limits_are_enforced() {
+    let url = format!("http://{}/huge", http_addr());
+    assert_eq!(failure_reason(hash_url(url).await), FailureReason::TooLarge);
+
+    let url = format!("http://{}/loop", http_addr());
+    assert_eq!(failure_reason(hash_url(url).await), FailureReason::RemoteUnavailable);
+
+    let url = format!("http://{}/missing", http_addr());
+    assert_eq!(failure_reason(hash_url(url).await), FailureReason::NotFound);
+}
@@ -0,0 +1,106 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+use task_scheduler::{
+    CancellationToken, HashAlgorithms,
+    config::ServerConfig,
+    protocol::{FailureReason, TaskResponse},
+    run_server_on,
+    sandbox::SandboxConfig,
+};
+
+/// A root holding `ok.txt`, a FIFO and symlinks, next to a directory
+/// holding `secret.txt` that clients must not reach.
+fn tree() -> &'static PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("sandbox-test-{}", std::process::id()));
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("ok.txt"), b"allowed").unwrap();
+        std::fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(root.join("ok.txt"), outside.join("inward")).unwrap();
+        let fifo = root.join("pipe");
+        assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        dir
+    })
+}
+
+fn sandboxed_server() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        let sandbox = SandboxConfig {
+            roots: vec![tree().join("root")],
+            ..SandboxConfig::default()
+        };
+        common::serve(ServerConfig::builder().workers(2).sandbox(sandbox).build().unwrap())
+    })
+}
+
+async fn hash(path: PathBuf) -> TaskResponse {
+    let mut stream = common::connect_to(sandboxed_server()).await;
+    let frame = common::hash_request(1, HashAlgorithms::SHA256, path.to_str().unwrap());
+    common::roundtrip(&mut stream, &frame).await
+}
+
+fn assert_refused(response: TaskResponse) {
+    match response {
+        TaskResponse::Failed {
+            reason: FailureReason::PathNotAllowed,
+            ..
+        } => {}
+        other => panic!("Expected a sandbox refusal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn files_inside_the_roots_are_hashed() {
+    let root = tree().join("root");
+    assert!(matches!(hash(root.join("ok.txt")).await, TaskResponse::Success { .. }));
+    // Symlinks are resolved: this one lives outside but points inside.
+    let inward = tree().join("outside").join("inward");
+    assert!(matches!(hash(inward).await, TaskResponse::Success { .. }));
+    assert!(matches!(
+        hash(root.join("missing.txt")).await,
+        TaskResponse::Failed { reason: FailureReason::NotFound, .. }
+    ));
+}
+
+#[tokio::test]
+async fn paths_escaping_the_roots_are_refused() {
+    let root = tree().join("root");
+    assert_refused(hash(tree().join("outside").join("secret.txt")).await);
+    assert_refused(hash(root.join("..").join("outside").join("secret.txt")).await);
+    assert_refused(hash(root.join("escape")).await);
+    assert_refused(hash(PathBuf::from("/etc/passwd")).await);
+    // Missing files outside the roots do not reveal whether they exist.
+    assert_refused(hash(tree().join("outside").join("missing.txt")).await);
+}
+
+#[tokio::test]
+async fn special_files_are_refused_by_default() {
+    assert_refused(hash(tree().join("root").join("pipe")).await);
+    assert_refused(hash(tree().join("root")).await);
+}
+
+#[tokio::test]
+async fn missing_roots_prevent_the_server_from_starting() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let sandbox = SandboxConfig {
+        roots: vec![tree().join("does-not-exist")],
+        ..SandboxConfig::default()
+    };
+    let config = ServerConfig::builder().sandbox(sandbox).build().unwrap();
+    let error = run_server_on(listener, config, CancellationToken::new())
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
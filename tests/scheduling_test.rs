@@ -0,0 +1,159 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use task_scheduler::{
+    HashAlgorithms,
+    auth::{AuthConfig, Credentials},
+    config::{ConfigError, ServerConfig},
+    protocol::{ProtocolMessage, TaskResponse, client_handshake_with, read_protocol},
+    queue::{SchedulingConfig, SchedulingPolicy},
+    quota::{Quota, QuotaConfig},
+};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+const KEY: &str = "correct horse battery staple";
+
+/// A 1 MiB file, slow enough to hash that responses arrive in the order
+/// their tasks ran.
+fn input() -> &'static str {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("scheduling-test-{}", std::process::id()));
+        std::fs::write(&path, vec![0x5a; 1 << 20]).unwrap();
+        path.to_str().unwrap().to_owned()
+    })
+}
+
+async fn connect(addr: std::net::SocketAddr, identity: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    client_handshake_with(&mut stream, Some(&Credentials::new(identity, KEY)))
+        .await
+        .unwrap();
+    stream
+}
+
+/// Reads `count` responses, recording `who` in `order` as each arrives.
+fn collect(
+    mut stream: TcpStream,
+    count: usize,
+    who: &'static str,
+    order: Arc<Mutex<Vec<&'static str>>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        for _ in 0..count {
+            match read_protocol(&mut stream).await.unwrap() {
+                ProtocolMessage::TaskResponse(TaskResponse::Success { id, .. }) => {
+                    // The task blocking the worker is not part of the race.
+                    if id != 0 {
+                        order.lock().unwrap().push(who);
+                    }
+                }
+                other => panic!("Expected a success, got {:?}", other),
+            }
+        }
+    })
+}
+
+/// Has a "bulk" client queue four tasks behind one blocking the only
+/// worker, then a "light" client queue one, and returns whose tasks ran in
+/// which order.
+async fn run(name: &str, scheduling: SchedulingConfig) -> Vec<&'static str> {
+    let auth = AuthConfig::default().with_key("bulk", KEY).with_key("light", KEY);
+    let quotas = QuotaConfig::default()
+        .with_tier("bulk", Quota::default())
+        .with_identity("bulk", "bulk");
+    let config = ServerConfig {
+        workers: 1,
+        auth: Some(auth),
+        quotas,
+        scheduling,
+        ..common::fifo_config()
+    };
+    let addr = common::serve(config);
+
+    let fifo = common::fifo(name);
+
+    let mut bulk = connect(addr, "bulk").await;
+    let mut light = connect(addr, "light").await;
+    bulk.write_all(&common::hash_request(0, HashAlgorithms::SHA256, fifo.to_str().unwrap()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    for id in 1..=4 {
+        bulk.write_all(&common::hash_request(id, HashAlgorithms::SHA256, input()))
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    light
+        .write_all(&common::hash_request(1, HashAlgorithms::SHA256, input()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let readers = [
+        collect(bulk, 5, "bulk", Arc::clone(&order)),
+        collect(light, 1, "light", Arc::clone(&order)),
+    ];
+    tokio::task::spawn_blocking(move || std::fs::write(fifo, b"done").unwrap());
+    for reader in readers {
+        reader.await.unwrap();
+    }
+    Arc::try_unwrap(order).unwrap().into_inner().unwrap()
+}
+
+#[tokio::test]
+async fn fifo_runs_tasks_in_submission_order() {
+    let scheduling = SchedulingConfig {
+        policy: SchedulingPolicy::Fifo,
+        ..SchedulingConfig::default()
+    };
+    assert_eq!(run("fifo", scheduling).await, ["bulk", "bulk", "bulk", "bulk", "light"]);
+}
+
+#[tokio::test]
+async fn clients_take_turns_by_default() {
+    assert_eq!(
+        run("round-robin", SchedulingConfig::default()).await,
+        ["bulk", "light", "bulk", "bulk", "bulk"]
+    );
+}
+
+#[tokio::test]
+async fn weighted_clients_run_several_tasks_per_turn() {
+    let scheduling = SchedulingConfig {
+        policy: SchedulingPolicy::Weighted,
+        weights: HashMap::from([(String::from("bulk"), 3)]),
+        ..SchedulingConfig::default()
+    };
+    assert_eq!(run("weighted", scheduling).await, ["bulk", "bulk", "bulk", "light", "bulk"]);
+}
+
+#[test]
+fn scheduling_settings_are_validated() {
+    let config = ServerConfig::from_toml_str(
+        "[scheduling]\npolicy = \"weighted\"\ndefault_weight = 2\n\n\
+         [scheduling.weights]\nbatch = 5\n\n\
+         [quotas.tiers.batch]\n",
+    )
+    .unwrap();
+    assert_eq!(config.scheduling.policy, SchedulingPolicy::Weighted);
+    assert_eq!(config.scheduling.weights["batch"], 5);
+    assert_eq!(ServerConfig::default().scheduling.policy, SchedulingPolicy::RoundRobin);
+
+    for invalid in [
+        "[scheduling.weights]\nmissing = 2\n",
+        "[scheduling]\ndefault_weight = 0\n",
+        "[scheduling.weights]\nbatch = 0\n\n[quotas.tiers.batch]\n",
+    ] {
+        assert!(matches!(
+            ServerConfig::from_toml_str(invalid),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+    assert!(ServerConfig::from_toml_str("[scheduling]\npolicy = \"lottery\"\n").is_err());
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use task_scheduler::{
+    CancellationToken, HashAlgorithms, ShutdownSummary,
+    config::ServerConfig,
+    protocol::{ProtocolMessage, ServerError, TaskResponse, read_protocol},
+    run_server_on,
+};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+fn start(config: ServerConfig) -> (SocketAddr, CancellationToken, JoinHandle<ShutdownSummary>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let listener = TcpListener::from_std(listener).unwrap();
+
+    let shutdown = CancellationToken::new();
+    let token = shutdown.clone();
+    let server = tokio::spawn(async move { run_server_on(listener, config, token).await.unwrap() });
+    (addr, shutdown, server)
+}
+
+/// Sends a request blocked on `fifo` and waits until the server dispatched it.
+async fn dispatch_slow_request(stream: &mut TcpStream, fifo: &str) {
+    stream
+        .write_all(&common::hash_request(1, HashAlgorithms::SHA256, fifo))
+        .await
+        .unwrap();
+    // Requests are read in order, so once this one is answered the slow one is queued.
+    let fast = common::hash_request(2, HashAlgorithms::SHA256, "Cargo.toml");
+    assert_eq!(common::roundtrip(stream, &fast).await.id(), 2);
+}
+
+async fn expect_shutdown_notice(stream: &mut TcpStream) {
+    match read_protocol(stream).await.unwrap() {
+        ProtocolMessage::Error(ServerError::ShuttingDown) => {}
+        other => panic!("Expected a shutdown notice, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn idle_clients_are_notified_and_listener_closes() {
+    let (addr, shutdown, server) = start(ServerConfig::default());
+    let mut stream = common::connect_to(addr).await;
+
+    shutdown.cancel();
+    expect_shutdown_notice(&mut stream).await;
+
+    let summary = server.await.unwrap();
+    assert_eq!(summary.connections, 1);
+    assert_eq!(summary.abandoned_tasks, 0);
+    assert!(!summary.timed_out);
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn clients_still_in_their_handshake_are_notified() {
+    let (addr, shutdown, server) = start(ServerConfig::default());
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    // Let the server accept the connection before it starts shutting down.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.cancel();
+    expect_shutdown_notice(&mut stream).await;
+    assert_eq!(server.await.unwrap().connections, 1);
+}
+
+#[tokio::test]
+async fn accepted_tasks_are_drained_before_closing() {
+    let fifo = common::fifo("drain.fifo");
+    let (addr, shutdown, server) = start(common::fifo_config());
+    let mut stream = common::connect_to(addr).await;
+    dispatch_slow_request(&mut stream, fifo.to_str().unwrap()).await;
+
+    shutdown.cancel();
+    let writer = fifo.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        std::fs::write(writer, b"finally").unwrap();
+    });
+
+    match read_protocol(&mut stream).await.unwrap() {
+        ProtocolMessage::TaskResponse(TaskResponse::Success { id: 1, .. }) => {}
+        other => panic!("Expected the drained response, got {:?}", other),
+    }
+    expect_shutdown_notice(&mut stream).await;
+
+    let summary = server.await.unwrap();
+    assert_eq!(summary.completed_tasks, 1);
+    assert!(!summary.timed_out);
+}
+
+#[tokio::test]
+async fn drain_deadline_abandons_stuck_tasks() {
+    let fifo = common::fifo("stuck.fifo");
+    let config = ServerConfig {
+        shutdown_timeout: Duration::from_millis(200),
+        ..common::fifo_config()
+    };
+    let (addr, shutdown, server) = start(config);
+    let mut stream = common::connect_to(addr).await;
+    dispatch_slow_request(&mut stream, fifo.to_str().unwrap()).await;
+
+    shutdown.cancel();
+    expect_shutdown_notice(&mut stream).await;
+
+    let summary = server.await.unwrap();
+    assert!(summary.timed_out);
+    assert_eq!(summary.abandoned_tasks, 1);
+
+    // Release the blocked worker thread. Its task was cancelled with the
+    // connection, so it may close the FIFO without reading from it.
+    let _ = std::fs::write(&fifo, b"late");
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    client::{Client, ClientError},
+    config::ServerConfig,
+    tls::{ClientTls, TlsConfig},
+};
+
+/// Certificates written once per test binary: a CA, a server certificate for
+/// `localhost`/`127.0.0.1` and a client certificate, all signed by the CA, plus
+/// an unrelated CA.
+fn pki() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("tls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ca = issuer();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        std::fs::write(dir.join("other-ca.pem"), issuer().pem()).unwrap();
+
+        let leaves = [
+            ("server", ExtendedKeyUsagePurpose::ServerAuth),
+            ("client", ExtendedKeyUsagePurpose::ClientAuth),
+        ];
+        for (name, usage) in leaves {
+            let mut params =
+                CertificateParams::new(vec![String::from("localhost"), String::from("127.0.0.1")])
+                    .unwrap();
+            params.extended_key_usages = vec![usage];
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &ca).unwrap();
+            std::fs::write(dir.join(format!("{}.pem", name)), cert.pem()).unwrap();
+            std::fs::write(dir.join(format!("{}.key", name)), key.serialize_pem()).unwrap();
+        }
+        dir
+    })
+}
+
+fn issuer() -> CertifiedIssuer<'static, KeyPair> {
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap()
+}
+
+fn serve_tls(client_ca: bool) -> String {
+    let dir = pki();
+    let tls = TlsConfig {
+        cert: dir.join("server.pem"),
+        key: dir.join("server.key"),
+        client_ca: client_ca.then(|| dir.join("ca.pem")),
+    };
+    let config = ServerConfig::builder().workers(2).tls(tls).build().unwrap();
+    common::serve(config).to_string()
+}
+
+async fn hash(client: &Client) -> Result<String, ClientError> {
+    client
+        .hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256)
+        .await
+}
+
+fn cargo_toml_digest() -> String {
+    format!("{:x}", Sha256::digest(std::fs::read("Cargo.toml").unwrap()))
+}
+
+#[tokio::test]
+async fn tls_client_hashes_through_tls_server() {
+    let addr = serve_tls(false);
+    let tls = ClientTls::new(pki().join("ca.pem")).unwrap();
+    let client = Client::builder(&addr).tls(tls.clone()).build();
+    assert_eq!(hash(&client).await.unwrap(), cargo_toml_digest());
+
+    let named = tls.with_server_name("localhost").unwrap();
+    let client = Client::builder(&addr).tls(named).build();
+    assert_eq!(hash(&client).await.unwrap(), cargo_toml_digest());
+}
+
+#[tokio::test]
+async fn untrusted_or_plaintext_clients_are_refused() {
+    let addr = serve_tls(false);
+
+    let plaintext = Client::builder(&addr).build();
+    assert!(matches!(hash(&plaintext).await, Err(ClientError::Protocol(_))));
+
+    let untrusted = ClientTls::new(pki().join("other-ca.pem")).unwrap();
+    let client = Client::builder(&addr).tls(untrusted).build();
+    assert!(matches!(hash(&client).await, Err(ClientError::Protocol(_))));
+}
+
+#[tokio::test]
+async fn mutual_tls_requires_a_client_certificate() {
+    let addr = serve_tls(true);
+    let dir = pki();
+
+    let anonymous = ClientTls::new(dir.join("ca.pem")).unwrap();
+    let client = Client::builder(&addr).tls(anonymous).build();
+    assert!(hash(&client).await.is_err());
+
+    let identified = ClientTls::new(dir.join("ca.pem"))
+        .unwrap()
+        .with_identity(dir.join("client.pem"), dir.join("client.key"))
+        .unwrap();
+    let client = Client::builder(&addr).tls(identified).build();
+    assert_eq!(hash(&client).await.unwrap(), cargo_toml_digest());
+}
+
+#[test]
+fn unusable_tls_files_are_reported() {
+    let dir = pki();
+    let swapped = TlsConfig {
+        cert: dir.join("server.key"),
+        key: dir.join("server.pem"),
+        client_ca: None,
+    };
+    assert!(swapped.acceptor().is_err());
+    assert!(ClientTls::new(dir.join("missing.pem")).is_err());
+}
@@ -0,0 +1,143 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+use std::time::Duration;
+use task_scheduler::{
+    CancellationToken, FilePath, HashAlgorithms, ShutdownSummary,
+    client::{Client, ClientError},
+    config::ServerConfig,
+    protocol::{ProtocolError, ServerError},
+    run_server,
+    unix::UnixConfig,
+};
+use tokio::task::JoinHandle;
+
+fn socket_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("unix-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Starts a server listening only on the given socket, once it is bound.
+async fn start(unix: UnixConfig) -> (CancellationToken, JoinHandle<std::io::Result<ShutdownSummary>>) {
+    let path = unix.path.clone();
+    let config = ServerConfig::builder()
+        .workers(2)
+        .tcp(false)
+        .unix(unix)
+        .build()
+        .unwrap();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(run_server(config, shutdown.clone()));
+    while std::os::unix::net::UnixStream::connect(&path).is_err() {
+        assert!(!server.is_finished(), "server failed to start");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    (shutdown, server)
+}
+
+async fn hash(path: &std::path::Path) -> Result<String, ClientError> {
+    Client::builder(format!("unix:{}", path.display()))
+        .build()
+        .hash(FilePath::Local(String::from("Cargo.toml")), HashAlgorithms::SHA256)
+        .await
+}
+
+fn own_uid() -> u32 {
+    std::fs::metadata(socket_path("")).unwrap().uid()
+}
+
+#[tokio::test]
+async fn local_clients_are_served_over_the_socket() {
+    let path = socket_path("served.sock");
+    let unix = UnixConfig {
+        mode: 0o660,
+        ..UnixConfig::new(&path)
+    };
+    let (shutdown, server) = start(unix).await;
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+    assert_eq!(hash(&path).await.unwrap().len(), 64);
+
+    shutdown.cancel();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists(), "socket file should be removed on shutdown");
+}
+
+#[tokio::test]
+async fn peers_are_checked_against_allowed_credentials() {
+    let allowed = socket_path("allowed.sock");
+    let unix = UnixConfig {
+        allowed_uids: vec![own_uid()],
+        ..UnixConfig::new(&allowed)
+    };
+    let (_shutdown, _server) = start(unix).await;
+    assert!(hash(&allowed).await.is_ok());
+
+    let denied = socket_path("denied.sock");
+    let unix = UnixConfig {
+        allowed_uids: vec![own_uid().wrapping_add(1)],
+        allowed_gids: vec![u32::MAX - 1],
+        ..UnixConfig::new(&denied)
+    };
+    let (_shutdown, _server) = start(unix).await;
+    match hash(&denied).await {
+        Err(ClientError::Protocol(ProtocolError::Refused(ServerError::Forbidden))) => {}
+        other => panic!("Expected a forbidden refusal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn stale_sockets_are_replaced_but_live_ones_are_kept() {
+    let path = socket_path("stale.sock");
+    let _ = std::fs::remove_file(&path);
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let (_shutdown, _server) = start(UnixConfig::new(&path)).await;
+    assert!(hash(&path).await.is_ok());
+
+    let config = ServerConfig::builder()
+        .tcp(false)
+        .unix(UnixConfig::new(&path))
+        .build()
+        .unwrap();
+    let error = run_server(config, CancellationToken::new()).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+}
+
+#[tokio::test]
+async fn other_files_at_the_socket_path_are_left_alone() {
+    let path = socket_path("not-a-socket");
+    std::fs::write(&path, b"precious").unwrap();
+
+    let config = ServerConfig::builder()
+        .tcp(false)
+        .unix(UnixConfig::new(&path))
+        .build()
+        .unwrap();
+    let error = run_server(config, CancellationToken::new()).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read(&path).unwrap(), b"precious");
+}
+
+#[test]
+fn unix_settings_are_validated() {
+    assert!(ServerConfig::builder().tcp(false).build().is_err());
+
+    let config = ServerConfig::from_toml_str(
+        "tcp = false\n[unix]\npath = \"/run/ts.sock\"\nmode = 0o640\nallowed_gids = [100]\n",
+    )
+    .unwrap();
+    let unix = config.unix.unwrap();
+    assert_eq!(unix.mode, 0o640);
+    assert!(unix.allowed_uids.is_empty());
+
+    let bad_mode = UnixConfig {
+        mode: 0o4755,
+        ..UnixConfig::new("/run/ts.sock")
+    };
+    assert!(ServerConfig::builder().unix(bad_mode).build().is_err());
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use sha3::{
+    Shake128, Shake256,
+    digest::{ExtendableOutput, Update},
+};
+use task_scheduler::{
+    FilePath, HashAlgorithms,
+    protocol::{FailureReason, HashingPacket, Priority, ProtocolMessage, TaskRequest, TaskResponse},
+};
+
+const CONTENT: &[u8] = b"extendable output functions squeeze as much as you ask";
+
+fn sample_file(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("xof-test-{}-{}", std::process::id(), name));
+    std::fs::write(&path, CONTENT).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+fn xof_request(algorithm: HashAlgorithms, path: &str, output_len: Option<u32>) -> Vec<u8> {
+    ProtocolMessage::TaskRequest(TaskRequest::HashPacket {
+        id: 1,
+        packet: HashingPacket {
+            algorithm,
+            path: FilePath::Local(String::from(path)),
+            output_len,
+            progress: false,
+        },
+        priority: Priority::Normal,
+    })
+    .into_packet()
+    .unwrap()
+}
+
+fn expected<X: Default + Update + ExtendableOutput>(len: usize) -> String {
+    let mut hasher = X::default();
+    hasher.update(CONTENT);
+    hasher
+        .finalize_boxed(len)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn digest_of(response: TaskResponse) -> String {
+    match response {
+        TaskResponse::Success { digest, .. } => digest,
+        other => panic!("Expected a digest, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn shake_uses_default_output_length() {
+    let path = sample_file("default");
+    let mut stream = common::connect().await;
+
+    let frame = xof_request(HashAlgorithms::SHAKE128, &path, None);
+    let digest = digest_of(common::roundtrip(&mut stream, &frame).await);
+    assert_eq!(digest, expected::<Shake128>(32));
+
+    let frame = xof_request(HashAlgorithms::SHAKE256, &path, None);
+    let digest = digest_of(common::roundtrip(&mut stream, &frame).await);
+    assert_eq!(digest, expected::<Shake256>(64));
+}
+
+#[tokio::test]
+async fn shake_honours_requested_output_length() {
+    let path = sample_file("custom");
+    let mut stream = common::connect().await;
+
+    let frame = xof_request(HashAlgorithms::SHAKE256, &path, Some(100));
+    let digest = digest_of(common::roundtrip(&mut stream, &frame).await);
+    assert_eq!(digest.len(), 200);
+    assert_eq!(digest, expected::<Shake256>(100));
+}
+
+#[tokio::test]
+async fn output_length_is_rejected_for_fixed_size_algorithms() {
+    let path = sample_file("fixed");
+    let mut stream = common::connect().await;
+
+    let frames = [
+        xof_request(HashAlgorithms::SHA256, &path, Some(16)),
+        xof_request(HashAlgorithms::SHAKE128, &path, Some(0)),
+    ];
+    for frame in frames {
+        match common::roundtrip(&mut stream, &frame).await {
+            TaskResponse::Failed { reason, .. } => {
+                assert_eq!(reason, FailureReason::InvalidRequest)
+            }
+            other => panic!("Expected a failure, got {:?}", other),
+        }
+    }
+}